@@ -0,0 +1,442 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Reads netlists in the Berkeley Logic Interchange Format (BLIF), as written by
+// abc and other synthesis tools. Every `.model` becomes a module like the ones
+// of a yosys JSON file, the first one is the top module.
+//
+// Supported are `.inputs`, `.outputs`, `.names`, `.latch` and instances of
+// cells or other models (`.gate` / `.subckt`). The function of a `.names`
+// table has to match one of the available single output cells, possibly with
+// its inputs in a different order. Latches need a clock: `re` and `fe` become
+// `DFF` and `DFFN`, `ah` becomes `DLATCH`.
+
+use crate::gate::{BasicCircuitDetails, BasicCircuitYada, CellLibrary};
+use crate::liberty::truth_table;
+use crate::loader::{
+    YosysJson, YosysJsonCell, YosysJsonModule, YosysJsonPort, YosysJsonPortDirection,
+};
+use itertools::Itertools;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+// A cell `.names` tables can be mapped to.
+struct Candidate {
+    cell: BasicCircuitYada,
+    truth_table: Vec<bool>,
+}
+
+// All combinational cells with a single output, library cells first.
+fn candidates(library: &CellLibrary) -> Vec<Candidate> {
+    library
+        .cells()
+        .chain(BasicCircuitYada::cells().iter().copied())
+        .filter(|c| {
+            !c.is_sequential() && !c.input_names().is_empty() && c.output_names().len() == 1
+        })
+        .map(|cell| Candidate {
+            cell,
+            truth_table: truth_table(&cell).into_iter().map(|row| row[0]).collect(),
+        })
+        .collect()
+}
+
+// A `.names` table: The value of the output for every combination of the
+// inputs (the first input is the most significant bit).
+struct Table {
+    inputs: Vec<String>,
+    output: String,
+    values: Vec<bool>,
+}
+
+impl Table {
+    fn new(signals: &[&str], cover: &[(&str, &str)]) -> Self {
+        let (output, inputs) = signals.split_last().unwrap();
+        let n = inputs.len();
+        // All rows either list where the output is 1 or where it is 0.
+        let on_set = !matches!(cover.first(), Some((_, value)) if *value != "1");
+        for &(pattern, value) in cover {
+            if pattern.len() != n || (value == "1") != on_set {
+                panic!("Invalid cover '{} {}' for {}", pattern, value, output);
+            }
+        }
+
+        let values = (0..1 << n)
+            .map(|row| {
+                let covered = cover.iter().any(|&(pattern, _)| {
+                    pattern.chars().enumerate().all(|(idx, c)| {
+                        let bit = row >> (n - 1 - idx) & 1 == 1;
+                        match c {
+                            '-' => true,
+                            '1' => bit,
+                            '0' => !bit,
+                            _ => panic!("Invalid cover '{}' for {}", pattern, output),
+                        }
+                    })
+                });
+                covered == on_set
+            })
+            .collect();
+        Self {
+            inputs: inputs.iter().map(|s| s.to_string()).collect(),
+            output: output.to_string(),
+            values,
+        }
+    }
+
+    // Drops the inputs the output doesn't depend on.
+    fn reduce(self) -> Self {
+        let n = self.inputs.len();
+        let values = &self.values;
+        let support = (0..n)
+            .filter(|&idx| {
+                let bit = 1 << (n - 1 - idx);
+                (0..1 << n).any(|row| values[row] != values[row ^ bit])
+            })
+            .collect::<Vec<_>>();
+
+        let m = support.len();
+        Self {
+            values: (0..1 << m)
+                .map(|reduced_row| {
+                    let row = support.iter().enumerate().fold(0, |row, (idx, &input)| {
+                        row | (reduced_row >> (m - 1 - idx) & 1) << (n - 1 - input)
+                    });
+                    values[row]
+                })
+                .collect(),
+            inputs: support
+                .iter()
+                .map(|&idx| self.inputs[idx].clone())
+                .collect(),
+            output: self.output,
+        }
+    }
+
+    // Returns a cell with the same function and the input of the table that
+    // is connected to each of its inputs.
+    fn find_cell(&self, candidates: &[Candidate]) -> Option<(BasicCircuitYada, Vec<usize>)> {
+        let n = self.inputs.len();
+        for candidate in candidates
+            .iter()
+            .filter(|c| c.cell.input_names().len() == n)
+        {
+            for permutation in (0..n).permutations(n) {
+                let matches = (0..1 << n).all(|row| {
+                    let cell_row =
+                        permutation
+                            .iter()
+                            .enumerate()
+                            .fold(0, |cell_row, (idx, &input)| {
+                                cell_row | (row >> (n - 1 - input) & 1) << (n - 1 - idx)
+                            });
+                    candidate.truth_table[cell_row] == self.values[row]
+                });
+                if matches {
+                    return Some((candidate.cell, permutation));
+                }
+            }
+        }
+        None
+    }
+}
+
+#[derive(Default)]
+struct Model {
+    name: String,
+    inputs: Vec<String>,
+    outputs: Vec<String>,
+    tables: Vec<Table>,
+    // Cell type and (port, signal) pairs.
+    cells: Vec<(String, Vec<(String, String)>)>,
+}
+
+impl Model {
+    fn into_module(self, candidates: &[Candidate], top: bool) -> YosysJsonModule {
+        let Model {
+            name,
+            inputs,
+            outputs,
+            tables: all_tables,
+            cells: instances,
+        } = self;
+
+        let mut constants = HashMap::new();
+        let mut tables = Vec::new();
+        for table in all_tables.into_iter().map(Table::reduce) {
+            if table.inputs.is_empty() {
+                constants.insert(table.output.clone(), table.values[0]);
+            } else {
+                tables.push(table);
+            }
+        }
+
+        let mut nets = HashMap::new();
+        let mut signal = |name: &str| match constants.get(name) {
+            Some(&value) => Value::from(if value { "1" } else { "0" }),
+            None => {
+                let next = nets.len() as u64 + 2;
+                Value::from(*nets.entry(name.to_string()).or_insert(next))
+            }
+        };
+
+        let mut cells = HashMap::new();
+        for table in tables {
+            let (cell, permutation) = table.find_cell(candidates).unwrap_or_else(|| {
+                panic!(
+                    "Model {}: The function of {} does not match any cell, map the design to the available cells first",
+                    name, table.output
+                )
+            });
+            let mut connections = cell
+                .input_names()
+                .iter()
+                .zip(permutation)
+                .map(|(name, input)| (name.to_string(), vec![signal(&table.inputs[input])]))
+                .collect::<HashMap<_, _>>();
+            connections.insert(
+                cell.output_names()[0].to_string(),
+                vec![signal(&table.output)],
+            );
+            cells.insert(
+                format!("c{}", cells.len()),
+                YosysJsonCell::new(cell.yosys_id(), connections),
+            );
+        }
+        for (cell_type, pins) in instances {
+            let connections = pins
+                .iter()
+                .map(|(port, net)| (port.clone(), vec![signal(net)]))
+                .collect();
+            cells.insert(
+                format!("c{}", cells.len()),
+                YosysJsonCell::new(&cell_type, connections),
+            );
+        }
+
+        let mut ports = HashMap::new();
+        for (names, direction) in &[
+            (&inputs, YosysJsonPortDirection::Input),
+            (&outputs, YosysJsonPortDirection::Output),
+        ] {
+            for port in names.iter() {
+                ports.insert(
+                    port.clone(),
+                    YosysJsonPort {
+                        direction: direction.clone(),
+                        bits: vec![signal(port)],
+                    },
+                );
+            }
+        }
+        YosysJsonModule::new(ports, cells, top)
+    }
+}
+
+// Joins continued lines and drops comments and empty lines.
+fn logical_lines(buf: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+    for line in buf.lines() {
+        let line = line.split('#').next().unwrap();
+        match line.trim_end().strip_suffix('\\') {
+            Some(line) => {
+                current.push_str(line);
+                current.push(' ');
+            }
+            None => {
+                current.push_str(line);
+                if !current.trim().is_empty() {
+                    lines.push(current.trim().to_string());
+                }
+                current = String::new();
+            }
+        }
+    }
+    lines
+}
+
+pub fn parse_blif(filepath: &str, library: &CellLibrary) -> std::io::Result<YosysJson> {
+    let mut file = File::open(filepath)?;
+    let mut buf = String::new();
+    file.read_to_string(&mut buf)?;
+    Ok(parse(filepath, &buf, library))
+}
+
+fn parse(filepath: &str, buf: &str, library: &CellLibrary) -> YosysJson {
+    let lines = logical_lines(buf);
+    let mut models = Vec::new();
+    let mut model = Model::default();
+    let mut idx = 0;
+    while idx < lines.len() {
+        let tokens = lines[idx].split_whitespace().collect::<Vec<_>>();
+        idx += 1;
+        match tokens[0] {
+            ".model" => {
+                model.name = tokens.get(1).unwrap_or(&"top").to_string();
+            }
+            ".inputs" => model
+                .inputs
+                .extend(tokens[1..].iter().map(|s| s.to_string())),
+            ".outputs" => model
+                .outputs
+                .extend(tokens[1..].iter().map(|s| s.to_string())),
+            ".names" => {
+                if tokens.len() < 2 {
+                    panic!("{}: .names without an output", filepath);
+                }
+                let mut cover = Vec::new();
+                while idx < lines.len() && !lines[idx].starts_with('.') {
+                    let row = lines[idx].split_whitespace().collect::<Vec<_>>();
+                    cover.push(match row.as_slice() {
+                        [value] => ("", *value),
+                        [pattern, value] => (*pattern, *value),
+                        _ => panic!("{}: Invalid cover '{}'", filepath, lines[idx]),
+                    });
+                    idx += 1;
+                }
+                model.tables.push(Table::new(&tokens[1..], &cover));
+            }
+            ".latch" => {
+                let (cell_type, enable) = match tokens.get(3).copied() {
+                    Some("re") => ("DFF", "C"),
+                    Some("fe") => ("DFFN", "C"),
+                    Some("ah") => ("DLATCH", "E"),
+                    Some(t) => panic!("{}: Latches of type {} are not supported", filepath, t),
+                    None => panic!(
+                        "{}: Latch {} has no clock, use '.latch <in> <out> re <clock>'",
+                        filepath, tokens[2]
+                    ),
+                };
+                let control = match tokens.get(4) {
+                    Some(&"NIL") | None => panic!("{}: Latch {} has no clock", filepath, tokens[2]),
+                    Some(control) => control.to_string(),
+                };
+                model.cells.push((
+                    cell_type.to_string(),
+                    vec![
+                        (enable.to_string(), control),
+                        ("D".to_string(), tokens[1].to_string()),
+                        ("Q".to_string(), tokens[2].to_string()),
+                    ],
+                ));
+            }
+            ".gate" | ".subckt" => {
+                let pins = tokens[2..]
+                    .iter()
+                    .map(|pin| match pin.split_once('=') {
+                        Some((port, signal)) => (port.to_string(), signal.to_string()),
+                        None => panic!("{}: Invalid connection {}", filepath, pin),
+                    })
+                    .collect();
+                model.cells.push((tokens[1].to_string(), pins));
+            }
+            ".end" => models.push(std::mem::take(&mut model)),
+            // Attributes and parameters written by yosys.
+            ".attr" | ".param" | ".cname" => {}
+            t => panic!("{}: Unsupported BLIF construct {}", filepath, t),
+        }
+    }
+    if !model.name.is_empty() {
+        models.push(model);
+    }
+
+    let candidates = candidates(library);
+    let modules = models
+        .into_iter()
+        .enumerate()
+        .map(|(idx, model)| (model.name.clone(), model.into_module(&candidates, idx == 0)))
+        .collect();
+    YosysJson::new("BLIF reader", modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn table(signals: &str, cover: &[(&str, &str)]) -> Table {
+        Table::new(&signals.split_whitespace().collect::<Vec<_>>(), cover)
+    }
+
+    #[test]
+    fn off_set_cover() {
+        let nand = table("a b y", &[("11", "0")]);
+        assert_eq!(nand.values, vec![true, true, true, false]);
+    }
+
+    #[test]
+    fn dont_cares() {
+        let or = table("a b y", &[("1-", "1"), ("-1", "1")]);
+        assert_eq!(or.values, vec![false, true, true, true]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid cover")]
+    fn mixed_cover() {
+        table("a b y", &[("11", "1"), ("00", "0")]);
+    }
+
+    #[test]
+    fn unused_inputs_are_dropped() {
+        let t = table("a b c y", &[("1-1", "1")]).reduce();
+        assert_eq!(t.inputs, vec!["a", "c"]);
+        assert_eq!(t.values, vec![false, false, false, true]);
+    }
+
+    #[test]
+    fn permuted_inputs() {
+        let candidates = candidates(&CellLibrary::default());
+        // b & !a is ANDNOT with its inputs swapped.
+        let t = table("a b y", &[("01", "1")]);
+        let (cell, permutation) = t.find_cell(&candidates).unwrap();
+        assert_eq!(cell.yosys_id(), "ANDNOT");
+        assert_eq!(permutation, vec![1, 0]);
+    }
+
+    #[test]
+    fn unknown_function() {
+        let candidates = candidates(&CellLibrary::default());
+        let t = table("a b c d e y", &[("11111", "1")]);
+        assert!(t.find_cell(&candidates).is_none());
+    }
+
+    #[test]
+    fn constant_tables() {
+        let json = parse(
+            "test.blif",
+            ".model top\n.outputs one zero\n.names one\n1\n.names zero\n.end\n",
+            &CellLibrary::default(),
+        );
+        let module = &json.modules["top"];
+        assert!(module.cells.is_empty());
+        assert_eq!(module.ports["one"].bits, vec![Value::from("1")]);
+        assert_eq!(module.ports["zero"].bits, vec![Value::from("0")]);
+    }
+
+    #[test]
+    fn latches() {
+        let json = parse(
+            "test.blif",
+            ".model top\n.inputs d c\n.outputs q\n.latch d q fe c 0\n.end\n",
+            &CellLibrary::default(),
+        );
+        let module = &json.modules["top"];
+        let cell = module.cells.values().next().unwrap();
+        assert_eq!(cell.cell_type, "DFFN");
+        assert_eq!(cell.connections["C"], module.ports["c"].bits);
+        assert_eq!(cell.connections["Q"], module.ports["q"].bits);
+    }
+}
@@ -17,10 +17,12 @@ use crate::gate::MeseconsGate;
 use byteorder::{BigEndian, WriteBytesExt};
 use deflate::write::ZlibEncoder;
 use deflate::Compression;
+use serde::Deserialize;
+use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufWriter, Write};
 
-const BLOCK_IDS: &[&str; 16] = &[
+const BLOCK_IDS: &[&str; 17] = &[
     "air",
     "stone",
     "mesecons_lamp:lamp_off",
@@ -39,11 +41,13 @@ const BLOCK_IDS: &[&str; 16] = &[
     "mesecons_extrawires:corner_off",
     "mesecons_extrawires:tjunction_off",
     "mesecons_extrawires:crossover_off",
+    // Connections between layers
+    "mesecons_extrawires:vertical_off",
     // For constant inputs
     "mesecons_torch:mesecon_torch_off",
 ];
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug, Deserialize)]
 pub enum CornerOrientation {
     LeftUp,
     LeftDown,
@@ -63,7 +67,7 @@ impl CornerOrientation {
     }
 }
 
-#[derive(Copy, Clone, PartialEq, Debug)]
+#[derive(Copy, Clone, PartialEq, Debug, Deserialize)]
 pub enum TRotation {
     LeftRightDown,
     LeftRightUp,
@@ -71,7 +75,15 @@ pub enum TRotation {
     LeftUpDown,
 }
 
+// A node that is copied verbatim from a hard macro and can be anything
+// minetest knows about.
 #[derive(Copy, Clone, PartialEq, Debug)]
+pub struct ForeignNode {
+    pub name: &'static str,
+    pub param2: u8,
+}
+
+#[derive(Copy, Clone, PartialEq, Debug, Deserialize)]
 pub enum BlockType {
     Air,
     WireH,
@@ -80,9 +92,14 @@ pub enum BlockType {
     WireT(TRotation),
     WireCorner(CornerOrientation),
     WireStar,
+    // Vertical wire connecting to the layers above and below.
+    Via,
     Gate(MeseconsGate),
 
     Constant,
+
+    #[serde(skip)]
+    Foreign(ForeignNode),
 }
 
 impl Default for BlockType {
@@ -108,6 +125,7 @@ impl BlockType {
             WireT(TRotation::RightUpDown) => '├',
             WireT(TRotation::LeftUpDown) => '┤',
             WireStar => '┼',
+            Via => '●',
 
             Gate(MeseconsGate::Input) => '░',
             Gate(MeseconsGate::Forward) => '»',
@@ -117,6 +135,8 @@ impl BlockType {
             Gate(_) => '▓',
 
             Constant => 'o',
+
+            Foreign(_) => '▒',
         }
     }
 
@@ -130,9 +150,12 @@ impl BlockType {
             WireCorner(_) => "mesecons_extrawires:corner_off",
             WireT(_) => "mesecons_extrawires:tjunction_off",
             WireStar => "mesecons:mesecon_off",
+            Via => "mesecons_extrawires:vertical_off",
             Gate(gate) => gate.mesecon_id(),
 
             Constant => "mesecons_torch:mesecon_torch_off",
+
+            Foreign(node) => node.name,
         }
     }
 
@@ -157,75 +180,230 @@ impl BlockType {
             Air => 0,
             WireCrossing => 0,
             WireStar => 0,
+            Via => 0,
             Constant => 0,
+
+            Foreign(node) => node.param2,
         }
     }
 }
 
-// Note that they do not need to be bigger than 64 * 1024 as that's a limitation
-// of minetest.
-const CANVAS_MAX_W: usize = 8 * 1024;
-const CANVAS_MAX_H: usize = 8 * 1024;
+// Side length of the square tiles the canvas is stored in. This matches the
+// size of a minetest mapblock.
+const CHUNK_SIZE: usize = 16;
 
+type Chunk = [BlockType; CHUNK_SIZE * CHUNK_SIZE];
+
+// The canvas is stored sparsely as a set of tiles, so memory usage scales with
+// the area that is actually occupied instead of the maximum schematic size.
+// Layer 0 is the main circuit layer, every further layer is placed one block
+// above the previous one.
 pub struct Canvas {
-    data: Box<[[BlockType; CANVAS_MAX_H]]>,
+    chunks: HashMap<(usize, usize, usize), Box<Chunk>>,
     width: usize,
     height: usize,
+    layers: usize,
 }
 
 impl Canvas {
     pub fn new() -> Self {
         Self {
-            data: vec![[BlockType::Air; CANVAS_MAX_H]; CANVAS_MAX_W].into_boxed_slice(),
+            chunks: HashMap::new(),
             height: 0,
             width: 0,
+            layers: 0,
         }
     }
 
-    pub fn set(&mut self, x: usize, y: usize, c: BlockType) {
+    fn check_bounds(x: usize, y: usize) {
+        // Note that the canvas does not need to be bigger than 64 * 1024 as
+        // that's a limitation of minetest.
         if x >= std::u16::MAX as _ || y >= std::u16::MAX as _ {
             panic!("Sorry, circuit too large for minetest (MTS format limitation)");
         }
-        if x >= CANVAS_MAX_W || y >= CANVAS_MAX_H {
-            panic!("{}", "Sorry, circuit too large for the internal canvas, consider increasing CANVAS_MAX_{W/H}");
-        }
+    }
+
+    fn chunk_index(x: usize, y: usize, layer: usize) -> ((usize, usize, usize), usize) {
+        (
+            (x / CHUNK_SIZE, y / CHUNK_SIZE, layer),
+            (y % CHUNK_SIZE) * CHUNK_SIZE + x % CHUNK_SIZE,
+        )
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, c: BlockType) {
+        self.set_layer(x, y, 0, c);
+    }
+
+    // Draws a straight wire, crossing any wire running in the other direction.
+    pub fn set_wire(&mut self, x: usize, y: usize, wire: BlockType) {
+        let crossing = matches!(
+            (self.get(x, y), wire),
+            (BlockType::WireH, BlockType::WireV) | (BlockType::WireV, BlockType::WireH)
+        );
+        self.set(
+            x,
+            y,
+            if crossing {
+                BlockType::WireCrossing
+            } else {
+                wire
+            },
+        );
+    }
+
+    pub fn get(&self, x: usize, y: usize) -> BlockType {
+        self.get_layer(x, y, 0)
+    }
+
+    pub fn set_layer(&mut self, x: usize, y: usize, layer: usize, c: BlockType) {
+        Self::check_bounds(x, y);
         if x > self.width {
             self.width = x;
         }
         if y > self.height {
             self.height = y;
         }
-        self.data[x][y] = c;
-    }
-
-    pub fn get(&self, x: usize, y: usize) -> BlockType {
-        if x >= std::u16::MAX as _ || y >= std::u16::MAX as _ {
-            panic!("Sorry, circuit too large for minetest (MTS format limitation)");
+        if layer > self.layers {
+            self.layers = layer;
         }
-        if x >= CANVAS_MAX_W || y >= CANVAS_MAX_H {
-            panic!("{}", "Sorry, circuit too large for the internal canvas, consider increasing CANVAS_MAX_{W/H}");
+        let (chunk, idx) = Self::chunk_index(x, y, layer);
+        if c == BlockType::Air && !self.chunks.contains_key(&chunk) {
+            // Nothing to do, don't allocate a new chunk for this.
+            return;
         }
-        self.data[x][y]
+        self.chunks
+            .entry(chunk)
+            .or_insert_with(|| Box::new([BlockType::Air; CHUNK_SIZE * CHUNK_SIZE]))[idx] = c;
+    }
+
+    pub fn get_layer(&self, x: usize, y: usize, layer: usize) -> BlockType {
+        Self::check_bounds(x, y);
+        let (chunk, idx) = Self::chunk_index(x, y, layer);
+        self.chunks
+            .get(&chunk)
+            .map(|c| c[idx])
+            .unwrap_or(BlockType::Air)
     }
 
     pub fn dimensions(&self) -> (usize, usize) {
         (self.width + 1, self.height + 1)
     }
 
+    // Number of blocks of the given type on all layers.
+    pub fn count(&self, block: BlockType) -> usize {
+        self.chunks
+            .values()
+            .map(|chunk| chunk.iter().filter(|&&b| b == block).count())
+            .sum()
+    }
+
+    // Names of all nodes copied from hard macros that are not in `BLOCK_IDS`.
+    fn foreign_node_names(&self) -> Vec<&'static str> {
+        let mut names = Vec::new();
+        for block in self.chunks.values().flat_map(|chunk| chunk.iter()) {
+            if let BlockType::Foreign(node) = block {
+                if !BLOCK_IDS.contains(&node.name) && !names.contains(&node.name) {
+                    names.push(node.name);
+                }
+            }
+        }
+        names
+    }
+
+    // Number of circuit layers (not including the stone floor).
+    pub fn layers(&self) -> usize {
+        self.layers + 1
+    }
+
     pub fn draw(&self) {
         let d = self.dimensions();
         let stdout = std::io::stdout();
         let mut lock = stdout.lock();
-        for line in 0..d.1 {
-            for c in 0..d.0 {
-                let c = &self.get(c, line).c();
-                let mut buf = [0u8; 4];
-                c.encode_utf8(&mut buf);
-                let buf = &buf[0..c.len_utf8()];
-                lock.write_all(&buf).unwrap();
+        for layer in 0..self.layers() {
+            if layer > 0 {
+                lock.write_all(format!("--- layer {} ---\n", layer).as_bytes())
+                    .unwrap();
+            }
+            for line in 0..d.1 {
+                for c in 0..d.0 {
+                    let c = &self.get_layer(c, line, layer).c();
+                    let mut buf = [0u8; 4];
+                    c.encode_utf8(&mut buf);
+                    let buf = &buf[0..c.len_utf8()];
+                    lock.write_all(&buf).unwrap();
+                }
+                lock.write_all(b"\n").unwrap();
+            }
+        }
+    }
+
+    // Checks whether the given block would connect to a via placed next to it.
+    fn connects_to_via(block: BlockType) -> bool {
+        !matches!(block, BlockType::Air | BlockType::WireV)
+    }
+
+    // Moves wire crossings onto the layer above: The horizontal wire stays on
+    // layer 0 while the vertical wire hops over it using vias. Returns the
+    // number of crossings that were removed.
+    pub fn bridge_crossings(&mut self) -> usize {
+        let d = self.dimensions();
+        let mut bridged = 0;
+        for x in 0..d.0 {
+            // Leave room for the via above the first crossing.
+            let mut y = 2;
+            while y < d.1 {
+                if self.get(x, y) != BlockType::WireCrossing {
+                    y += 1;
+                    continue;
+                }
+
+                // Find all crossings directly below each other, they can be
+                // bridged at once.
+                let y0 = y;
+                while self.get(x, y + 1) == BlockType::WireCrossing {
+                    y += 1;
+                }
+                let y1 = y;
+                y += 1;
+
+                // The vias replace the straight wires before and after the
+                // crossings.
+                let (via_top, via_bottom) = (y0 - 1, y1 + 1);
+                if self.get(x, via_top) != BlockType::WireV
+                    || self.get(x, via_bottom) != BlockType::WireV
+                {
+                    continue;
+                }
+
+                // Vias connect to all of their neighbours, so make sure that
+                // they don't touch anything else on either layer.
+                let is_isolated = |y: usize| {
+                    (0..2).all(|layer| {
+                        (x == 0 || !Self::connects_to_via(self.get_layer(x - 1, y, layer)))
+                            && !Self::connects_to_via(self.get_layer(x + 1, y, layer))
+                    })
+                };
+                if !is_isolated(via_top)
+                    || !is_isolated(via_bottom)
+                    || (via_top..=via_bottom).any(|y| self.get_layer(x, y, 1) != BlockType::Air)
+                    || self.get_layer(x, via_top - 1, 1) != BlockType::Air
+                    || self.get_layer(x, via_bottom + 1, 1) != BlockType::Air
+                {
+                    continue;
+                }
+
+                for y in y0..=y1 {
+                    self.set(x, y, BlockType::WireH);
+                    self.set_layer(x, y, 1, BlockType::WireV);
+                }
+                for &y in &[via_top, via_bottom] {
+                    self.set(x, y, BlockType::Via);
+                    self.set_layer(x, y, 1, BlockType::Via);
+                }
+                bridged += y1 - y0 + 1;
             }
-            lock.write_all(b"\n").unwrap();
         }
+        bridged
     }
 
     pub fn set_channel_wires(&mut self, ops: &[ChannelSubState], x: &mut u32) {
@@ -249,10 +427,15 @@ impl Canvas {
             }
 
             for op in state.wires.iter() {
+                if op.layer > 0 {
+                    continue;
+                }
+
                 let WireConnection {
                     from: source,
                     to: mut destination,
                     mode: op,
+                    ..
                 } = op.clone();
 
                 if op == ChannelOp::Copy {
@@ -376,19 +559,44 @@ impl Canvas {
                     );
                 }
             }
+
+            for op in state.wires.iter().filter(|op| op.layer > 0) {
+                self.set_layered_wire(*x as _, op);
+            }
             *x += 1 + CHANNEL_WIRE_PADDING as u32;
         }
     }
 
+    // Draws a wire whose vertical part is placed on a higher layer. It is
+    // connected to the tracks on layer 0 using vias at the source and
+    // destination pins.
+    fn set_layered_wire(&mut self, x: usize, wire: &WireConnection) {
+        let endpoints = || wire.to.iter().chain(std::iter::once(&wire.from));
+        let min = *endpoints().min().unwrap();
+        let max = *endpoints().max().unwrap();
+
+        for y in min..=max {
+            if endpoints().any(|&e| e == y) {
+                for layer in 0..=wire.layer {
+                    self.set_layer(x, y, layer, BlockType::Via);
+                }
+            } else {
+                self.set_layer(x, y, wire.layer, BlockType::WireV);
+            }
+        }
+    }
+
     pub fn generate_lua_schematic(&self, fname: &str) -> std::io::Result<()> {
         let d = self.dimensions();
+        // Stone floor + all circuit layers.
+        let h = self.layers() + 1;
         let mut file = File::create(fname)?;
         file.write_all("schematic = {\n".as_bytes())?;
-        file.write_all(format!("\tsize = {{x={}, y=2, z={}}},\n", d.1, d.0).as_bytes())?;
+        file.write_all(format!("\tsize = {{x={}, y={}, z={}}},\n", d.1, h, d.0).as_bytes())?;
         file.write_all("\tdata = {\n".as_bytes())?;
 
         for x in 0..d.0 {
-            for z in 0..2 {
+            for z in 0..h {
                 for y in 0..d.1 {
                     file.write_all(
                         if z == 0 {
@@ -396,7 +604,7 @@ impl Canvas {
                         } else {
                             format!(
                                 "\t\t{{name=\"{}\", param2=param2}},\n",
-                                self.get(x, y).minetest_type()
+                                self.get_layer(x, y, z - 1).minetest_type()
                             )
                         }
                         .as_bytes(),
@@ -418,6 +626,8 @@ impl Canvas {
         if d.1 > std::i16::MAX as _ || d.0 >= std::i16::MAX as _ {
             panic!("Schematic too big to export to a mts :/");
         }
+        // Stone floor + all circuit layers.
+        let h = self.layers() + 1;
 
         let mut file = File::create(fname)?;
 
@@ -425,12 +635,20 @@ impl Canvas {
         file.write_u16::<BigEndian>(1)?; // Version 1
 
         file.write_i16::<BigEndian>(d.1 as i16)?;
-        file.write_i16::<BigEndian>(2)?;
+        file.write_i16::<BigEndian>(h as i16)?;
         file.write_i16::<BigEndian>(d.0 as i16)?;
 
         // No need to do the prob table as we're totally old.
         // Write # node names.
-        file.write_u16::<BigEndian>(BLOCK_IDS.len() as u16)?;
+        let names = BLOCK_IDS
+            .iter()
+            .copied()
+            .chain(self.foreign_node_names())
+            .collect::<Vec<_>>();
+        if names.len() > std::u16::MAX as usize {
+            panic!("Too many different node types to serialize.");
+        }
+        file.write_u16::<BigEndian>(names.len() as u16)?;
 
         let serialize_string = |f: &mut File, s: &str| -> std::io::Result<()> {
             if s.len() > std::u16::MAX as usize {
@@ -442,28 +660,27 @@ impl Canvas {
             Ok(())
         };
 
-        for b in BLOCK_IDS.iter() {
+        for b in names.iter() {
             serialize_string(&mut file, b)?;
         }
 
         let mut encoder = BufWriter::new(ZlibEncoder::new(file, Compression::Best));
 
         // Generate reverse lookup table for block ids.
-        let mut block_lookup_table: std::collections::HashMap<&'static str, usize> =
-            std::collections::HashMap::new();
-        for (idx, val) in BLOCK_IDS.iter().enumerate() {
+        let mut block_lookup_table: HashMap<&'static str, usize> = HashMap::new();
+        for (idx, val) in names.iter().enumerate() {
             block_lookup_table.insert(val, idx);
         }
 
         println!(" [+] Writing node types");
         // Write node types.
         for x in 0..d.0 {
-            for z in 0..2 {
+            for z in 0..h {
                 for y in 0..d.1 {
                     let t = if z == 0 {
                         "stone"
                     } else {
-                        self.get(x, y).minetest_type()
+                        self.get_layer(x, y, z - 1).minetest_type()
                     };
 
                     encoder.write_u16::<BigEndian>(
@@ -478,7 +695,7 @@ impl Canvas {
 
         println!(" [+] Writing param1");
         // Write param1
-        for _ in 0..2 {
+        for _ in 0..h {
             for _ in 0..d.0 {
                 for _ in 0..d.1 {
                     encoder.write_u8(0)?;
@@ -489,12 +706,12 @@ impl Canvas {
         println!(" [+] Writing param2");
         // Write param2
         for x in 0..d.0 {
-            for z in 0..2 {
+            for z in 0..h {
                 for y in 0..d.1 {
                     encoder.write_u8(if z == 0 {
                         0
                     } else {
-                        self.get(x, y).get_param2()
+                        self.get_layer(x, y, z - 1).get_param2()
                     })?;
                 }
             }
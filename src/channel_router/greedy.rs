@@ -0,0 +1,281 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Greedy channel router, loosely following Rivest and Fiduccia.
+//
+// The channel is scanned column by column. In every column we
+// 1) connect nets to as many of their free target pins as possible (splitting
+//    the net if it is required on more than one pin),
+// 2) move (dogleg) nets that block target pins of other nets out of the way,
+//    preferring free tracks close to their own targets,
+// 3) move (dogleg) the remaining nets as close to their targets as possible,
+// 4) widen the channel if none of the above was possible.
+// Tracks that carry a net that is not required anymore are simply cut off.
+
+use super::*;
+
+// Returns the rows that still have to be connected to `net`.
+fn open_targets(state: &ChannelLayout, end: &ChannelLayout, net: usize) -> Vec<usize> {
+    (0..end.len())
+        .filter(|&idx| end[idx] == ChannelState::Net(net) && state[idx] != end[idx])
+        .collect()
+}
+
+fn is_open_target(state: &ChannelLayout, end: &ChannelLayout, idx: usize) -> bool {
+    idx < end.len() && end[idx].contains_net() && state[idx] != end[idx]
+}
+
+fn rows_with_net(state: &ChannelLayout, net: usize) -> Vec<usize> {
+    (0..state.len())
+        .filter(|&idx| state[idx] == ChannelState::Net(net))
+        .collect()
+}
+
+// Cuts off tracks that are not required anymore, returns the affected rows.
+fn cut_unneeded_tracks(state: &mut [ChannelState], end: &ChannelLayout) -> Vec<usize> {
+    let mut cut = Vec::new();
+    for idx in 0..state.len() {
+        let net = match state[idx] {
+            ChannelState::Net(net) => net,
+            _ => continue,
+        };
+
+        // Already at its final position.
+        if idx < end.len() && end[idx] == state[idx] {
+            continue;
+        }
+
+        let required = !open_targets(state, end, net).is_empty();
+        // Copies that block another net can go as long as there is another one.
+        let blocking = is_open_target(state, end, idx) && rows_with_net(state, net).len() > 1;
+        if !required || blocking {
+            state[idx] = ChannelState::Free;
+            cut.push(idx);
+        }
+    }
+    cut
+}
+
+pub fn route_channel_greedy(
+    start: &ChannelLayout,
+    end: &ChannelLayout,
+    layers: usize,
+) -> Vec<ChannelSubState> {
+    let mut state = start.to_owned();
+    // Expand the state to be at least end.len() wide.
+    while state.len() < end.len() {
+        state.push(ChannelState::Free);
+    }
+
+    // The stubs in front of the channel are already drawn, so cut tracks must
+    // not be reused by vias in the first column.
+    let mut via_rows = cut_unneeded_tracks(&mut state, end);
+    let mut steps: Vec<ChannelSubState> = Vec::new();
+
+    loop {
+        let mut ranges = LayerRanges::new(layers, via_rows);
+        let mut wires = Vec::new();
+
+        let mut nets = (0..end.len())
+            .filter(|&idx| is_open_target(&state, end, idx))
+            .filter_map(|idx| match end[idx] {
+                ChannelState::Net(net) => Some(net),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        nets.sort();
+        nets.dedup();
+
+        // 1) Connect nets to their free target pins.
+        let mut candidates = Vec::new();
+        for &net in &nets {
+            let targets = open_targets(&state, end, net)
+                .into_iter()
+                .filter(|&idx| !state[idx].contains_net())
+                .collect::<Vec<_>>();
+            if targets.is_empty() {
+                continue;
+            }
+
+            let min = *targets.iter().min().unwrap();
+            let max = *targets.iter().max().unwrap();
+            let from = rows_with_net(&state, net)
+                .into_iter()
+                .min_by_key(|&idx| cmp::max(max, idx) - cmp::min(min, idx))
+                .unwrap_or_else(|| panic!("Required net {} not found", net));
+            candidates.push((net, from, targets));
+        }
+        candidates.sort_by_key(|(_, from, targets)| {
+            let min = cmp::min(*from, *targets.iter().min().unwrap());
+            let max = cmp::max(*from, *targets.iter().max().unwrap());
+            max - min
+        });
+
+        for (net, from, mut targets) in candidates {
+            let layer = match ranges.find_layer(from, &targets, usize::MAX) {
+                Some(layer) => layer,
+                None => {
+                    // Split the net, connecting the closest pin that fits.
+                    targets.sort_by_key(|&idx| (from as isize - idx as isize).abs());
+                    match targets.iter().find_map(|&idx| {
+                        ranges
+                            .find_layer(from, &[idx], usize::MAX)
+                            .map(|l| (idx, l))
+                    }) {
+                        Some((idx, layer)) => {
+                            targets = vec![idx];
+                            layer
+                        }
+                        None => continue,
+                    }
+                }
+            };
+
+            let mode = if from < end.len() && end[from] == state[from] {
+                ChannelOp::Copy
+            } else {
+                state[from] = ChannelState::Free;
+                ChannelOp::Move
+            };
+            ranges.reserve(layer, from, &targets);
+            for &to in &targets {
+                state[to] = ChannelState::Net(net);
+            }
+            wires.push(WireConnection {
+                from,
+                to: targets,
+                mode,
+                layer,
+            });
+        }
+
+        // 2) Move nets that are in the way to free tracks.
+        let mut parking = (0..state.len())
+            .filter(|&idx| !state[idx].contains_net())
+            .filter(|&idx| idx >= end.len() || !end[idx].contains_net())
+            .collect::<Vec<_>>();
+        let blocked = (0..end.len())
+            .filter(|&idx| is_open_target(&state, end, idx) && state[idx].contains_net())
+            .collect::<Vec<_>>();
+
+        for &from in &blocked {
+            let net = match state[from] {
+                ChannelState::Net(net) => net,
+                _ => continue,
+            };
+            let targets = open_targets(&state, end, net);
+            if targets.is_empty() {
+                // Connected in this column already, cut off afterwards.
+                continue;
+            }
+            let task = Task {
+                net,
+                from,
+                to: targets,
+            };
+            parking.sort_by_key(|&idx| task.eviction_cost(idx));
+
+            let found = parking.iter().enumerate().find_map(|(k, &idx)| {
+                ranges
+                    .find_layer(from, &[idx], usize::MAX)
+                    .map(|layer| (k, idx, layer))
+            });
+            if let Some((k, to, layer)) = found {
+                parking.remove(k);
+                ranges.reserve(layer, from, &[to]);
+                state[from] = ChannelState::Free;
+                state[to] = ChannelState::Net(net);
+                wires.push(WireConnection {
+                    from,
+                    to: vec![to],
+                    mode: ChannelOp::Move,
+                    layer,
+                });
+            }
+        }
+
+        // 3) Move the remaining nets towards their closest target. Nets are
+        // only moved to rows that are not a target of another net, so they
+        // never block anything.
+        let mut remaining = (0..state.len())
+            .filter(|&idx| state[idx].contains_net())
+            .filter(|&idx| idx >= end.len() || end[idx] != state[idx])
+            .filter(|&idx| !wires.iter().any(|w| w.from == idx || w.to.contains(&idx)))
+            .filter_map(|idx| {
+                let net = match state[idx] {
+                    ChannelState::Net(net) => net,
+                    _ => return None,
+                };
+                open_targets(&state, end, net)
+                    .into_iter()
+                    .min_by_key(|&target| idx.abs_diff(target))
+                    .map(|target| (idx, net, target))
+            })
+            .collect::<Vec<_>>();
+        remaining.sort_by_key(|&(from, _, target)| cmp::Reverse(from.abs_diff(target)));
+
+        for (from, net, target) in remaining {
+            let rows = if from < target {
+                (from + 1..=target).rev().collect::<Vec<_>>()
+            } else {
+                (target..from).collect::<Vec<_>>()
+            };
+            let found = rows
+                .into_iter()
+                .filter(|&idx| !state[idx].contains_net())
+                .filter(|&idx| {
+                    idx >= end.len()
+                        || !end[idx].contains_net()
+                        || end[idx] == ChannelState::Net(net)
+                })
+                .find_map(|idx| {
+                    ranges
+                        .find_layer(from, &[idx], usize::MAX)
+                        .map(|layer| (idx, layer))
+                });
+            if let Some((to, layer)) = found {
+                ranges.reserve(layer, from, &[to]);
+                state[from] = ChannelState::Free;
+                state[to] = ChannelState::Net(net);
+                wires.push(WireConnection {
+                    from,
+                    to: vec![to],
+                    mode: ChannelOp::Move,
+                    layer,
+                });
+            }
+        }
+
+        // 4) Nothing could be done, so there is no space left.
+        if wires.is_empty() && !blocked.is_empty() {
+            println!("[!] No free positions found, expanding channel");
+            for _ in 0..(blocked.len() / 10 + 1) {
+                state.push(ChannelState::Free);
+            }
+            via_rows = ranges.blocked_via_rows;
+            continue;
+        }
+
+        cut_unneeded_tracks(&mut state, end);
+        via_rows = ranges.via_rows;
+        steps.push(ChannelSubState {
+            wires,
+            occupancy_map: occupancy_map(&state),
+        });
+
+        if (0..state.len()).all(|idx| !is_open_target(&state, end, idx)) {
+            return steps;
+        }
+    }
+}
@@ -0,0 +1,262 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Constrained left-edge channel router with doglegs.
+//
+// Our channels are transposed compared to the textbook version: pins are on
+// the left and right side, and every step of the channel is a "track" that
+// holds the vertical trunks of the nets.
+// - Trunks in the same step must not overlap (horizontal constraints), this is
+//   checked using `LayerRanges` so that higher layers can be used as well.
+// - If a row carries net A on the left side and is a target of net B on the
+//   right side, A has to leave the row in an earlier step than B arrives
+//   (vertical constraint graph).
+// Cycles in the vertical constraint graph are broken by doglegs: the net is
+// first moved to a free row and routed to its targets from there later on.
+// Trunks are then assigned to steps in left-edge order, as soon as all of
+// their predecessors are routed.
+
+use super::*;
+
+#[derive(Debug)]
+struct Subnet {
+    net: usize,
+    from: usize,
+    to: Vec<usize>,
+    mode: ChannelOp,
+}
+
+impl Subnet {
+    fn range(&self) -> (usize, usize) {
+        let endpoints = || self.to.iter().chain(std::iter::once(&self.from));
+        (*endpoints().min().unwrap(), *endpoints().max().unwrap())
+    }
+}
+
+// Returns the predecessors of every subnet in the vertical constraint graph.
+fn vertical_constraints(subnets: &[Subnet], state: &ChannelLayout) -> Vec<Vec<usize>> {
+    let mut arriving = HashMap::new();
+    let mut leaving = HashMap::new();
+    for (idx, subnet) in subnets.iter().enumerate() {
+        for &to in &subnet.to {
+            arriving.insert(to, idx);
+        }
+        // Only rows that are occupied at the start of the channel, doglegs
+        // are free there.
+        if subnet.mode == ChannelOp::Move && state[subnet.from].contains_net() {
+            leaving.insert(subnet.from, idx);
+        }
+    }
+
+    subnets
+        .iter()
+        .map(|subnet| {
+            // Targets have to be vacated, doglegs have to be reached first.
+            let dogleg = if state[subnet.from].contains_net() {
+                None
+            } else {
+                arriving.get(&subnet.from).copied()
+            };
+            subnet
+                .to
+                .iter()
+                .filter_map(|to| leaving.get(to).copied())
+                .chain(dogleg)
+                .collect()
+        })
+        .collect()
+}
+
+// Returns all subnets that are part of or depend on a cycle.
+fn unresolvable_subnets(predecessors: &[Vec<usize>]) -> Vec<usize> {
+    let mut resolved = vec![false; predecessors.len()];
+    loop {
+        let ready = (0..predecessors.len())
+            .filter(|&idx| !resolved[idx] && predecessors[idx].iter().all(|&p| resolved[p]))
+            .collect::<Vec<_>>();
+        if ready.is_empty() {
+            break;
+        }
+        for idx in ready {
+            resolved[idx] = true;
+        }
+    }
+    (0..predecessors.len())
+        .filter(|&idx| !resolved[idx])
+        .collect()
+}
+
+pub fn route_channel_left_edge(
+    start: &ChannelLayout,
+    end: &ChannelLayout,
+    layers: usize,
+) -> Vec<ChannelSubState> {
+    let mut state = start.to_owned();
+    // Expand the state to be at least end.len() wide.
+    while state.len() < end.len() {
+        state.push(ChannelState::Free);
+    }
+
+    // One subnet per net, starting at the copy closest to its targets.
+    let mut subnets = Vec::new();
+    let mut nets = end
+        .iter()
+        .filter_map(|v| match v {
+            ChannelState::Net(net) => Some(*net),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    nets.sort();
+    nets.dedup();
+    for net in nets {
+        let to = (0..end.len())
+            .filter(|&idx| end[idx] == ChannelState::Net(net) && state[idx] != end[idx])
+            .collect::<Vec<_>>();
+        if to.is_empty() {
+            continue;
+        }
+
+        let min = *to.iter().min().unwrap();
+        let max = *to.iter().max().unwrap();
+        let from = (0..state.len())
+            .filter(|&idx| state[idx] == ChannelState::Net(net))
+            .min_by_key(|&idx| cmp::max(max, idx) - cmp::min(min, idx))
+            .unwrap_or_else(|| panic!("Required net {} not found", net));
+        let mode = if from < end.len() && end[from] == state[from] {
+            ChannelOp::Copy
+        } else {
+            ChannelOp::Move
+        };
+        subnets.push(Subnet {
+            net,
+            from,
+            to,
+            mode,
+        });
+    }
+
+    // Cut off all tracks that are neither routed any further nor final.
+    let mut via_rows = Vec::new();
+    for idx in 0..state.len() {
+        if state[idx].contains_net()
+            && (idx >= end.len() || end[idx] != state[idx])
+            && subnets.iter().all(|s| s.from != idx)
+        {
+            state[idx] = ChannelState::Free;
+            // The stubs in front of the channel are already drawn, so cut
+            // tracks must not be reused by vias in the first step.
+            via_rows.push(idx);
+        }
+    }
+
+    // Break cycles in the vertical constraint graph using doglegs.
+    let mut parking = (0..state.len())
+        .filter(|&idx| !state[idx].contains_net())
+        .filter(|&idx| idx >= end.len() || !end[idx].contains_net())
+        .collect::<Vec<_>>();
+    let mut predecessors = vertical_constraints(&subnets, &state);
+    loop {
+        let unresolvable = unresolvable_subnets(&predecessors);
+        if unresolvable.is_empty() {
+            break;
+        }
+
+        // Split the subnet that blocks most of the others.
+        let idx = *unresolvable
+            .iter()
+            .max_by_key(|&&idx| {
+                unresolvable
+                    .iter()
+                    .filter(|&&other| predecessors[other].contains(&idx))
+                    .count()
+            })
+            .unwrap();
+
+        if parking.is_empty() {
+            println!("[!] No free positions found, expanding channel");
+            state.push(ChannelState::Free);
+            parking.push(state.len() - 1);
+        }
+        let task = Task {
+            net: subnets[idx].net,
+            from: subnets[idx].from,
+            to: subnets[idx].to.clone(),
+        };
+        let (k, &dogleg) = parking
+            .iter()
+            .enumerate()
+            .min_by_key(|(_, &p)| task.eviction_cost(p))
+            .unwrap();
+        parking.remove(k);
+
+        subnets.push(Subnet {
+            net: task.net,
+            from: dogleg,
+            to: task.to,
+            mode: ChannelOp::Move,
+        });
+        subnets[idx].to = vec![dogleg];
+        predecessors = vertical_constraints(&subnets, &state);
+    }
+
+    // Assign trunks to steps in left-edge order.
+    let mut order = (0..subnets.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&idx| subnets[idx].range());
+    let mut routed = vec![false; subnets.len()];
+    let mut steps: Vec<ChannelSubState> = Vec::new();
+
+    loop {
+        let mut ranges = LayerRanges::new(layers, via_rows);
+        let mut wires = Vec::new();
+        let mut routed_now = Vec::new();
+
+        for &idx in &order {
+            if routed[idx] || predecessors[idx].iter().any(|&p| !routed[p]) {
+                continue;
+            }
+
+            let subnet = &subnets[idx];
+            if let Some(layer) = ranges.find_layer(subnet.from, &subnet.to, usize::MAX) {
+                ranges.reserve(layer, subnet.from, &subnet.to);
+                wires.push(WireConnection {
+                    from: subnet.from,
+                    to: subnet.to.clone(),
+                    mode: subnet.mode,
+                    layer,
+                });
+                routed_now.push(idx);
+            }
+        }
+
+        for idx in routed_now {
+            let subnet = &subnets[idx];
+            if subnet.mode == ChannelOp::Move {
+                state[subnet.from] = ChannelState::Free;
+            }
+            for &to in &subnet.to {
+                state[to] = ChannelState::Net(subnet.net);
+            }
+            routed[idx] = true;
+        }
+
+        via_rows = ranges.via_rows;
+        steps.push(ChannelSubState {
+            wires,
+            occupancy_map: occupancy_map(&state),
+        });
+        if routed.iter().all(|&r| r) {
+            return steps;
+        }
+    }
+}
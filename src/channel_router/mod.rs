@@ -14,6 +14,15 @@
 
 use std::cmp;
 use std::collections::HashMap;
+use std::convert::TryFrom;
+
+mod greedy;
+mod left_edge;
+mod negotiated;
+
+pub use greedy::route_channel_greedy;
+pub use left_edge::route_channel_left_edge;
+pub use negotiated::{min_channel_width, route_channel_negotiated};
 
 struct Ranges {
     ranges: Vec<std::ops::Range<usize>>,
@@ -34,15 +43,78 @@ impl Ranges {
         (start..=end).any(|v| self.ranges.iter().any(|r| r.contains(&v)))
     }
 
-    fn contains_range(&self, range: &std::ops::Range<usize>) -> bool {
-        self.contains(range.start, range.end)
-    }
-
     fn range_sum(&self) -> usize {
         self.ranges.iter().map(|r| r.end - r.start).sum()
     }
 }
 
+// Occupied parts of the channel for every routing layer of the current step.
+//
+// Wires on layer 0 are drawn as before. Wires on higher layers are connected
+// to the tracks on layer 0 by vias at their end points. As vias connect to all
+// of their neighbours, they need one block of free space around them.
+struct LayerRanges {
+    layers: Vec<Ranges>,
+    // Rows of vias in the previous step (vias next to each other would connect).
+    blocked_via_rows: Vec<usize>,
+    // Rows of vias in the current step.
+    via_rows: Vec<usize>,
+}
+
+impl LayerRanges {
+    fn new(layers: usize, blocked_via_rows: Vec<usize>) -> Self {
+        Self {
+            layers: (0..layers).map(|_| Ranges::new()).collect(),
+            blocked_via_rows,
+            via_rows: Vec::new(),
+        }
+    }
+
+    // Finds the lowest layer a wire between the given rows could be placed on,
+    // skipping layers where more than `max_range_sum` rows are used already.
+    fn find_layer(&self, from: usize, to: &[usize], max_range_sum: usize) -> Option<usize> {
+        let endpoints = || to.iter().chain(std::iter::once(&from));
+        let min = *endpoints().min().unwrap();
+        let max = *endpoints().max().unwrap();
+
+        (0..self.layers.len()).find(|&layer| {
+            if self.layers[layer].range_sum() > max_range_sum {
+                return false;
+            }
+            if layer == 0 {
+                return !self.layers[0].contains(min, max + 1);
+            }
+
+            !self.layers[layer].contains(min.saturating_sub(1), max + 1)
+                && endpoints().all(|&e| {
+                    !self.blocked_via_rows.contains(&e)
+                        && self.layers[..layer]
+                            .iter()
+                            .all(|r| !r.contains(e.saturating_sub(1), e + 1))
+                })
+        })
+    }
+
+    fn reserve(&mut self, layer: usize, from: usize, to: &[usize]) {
+        let endpoints = || to.iter().chain(std::iter::once(&from));
+        let min = *endpoints().min().unwrap();
+        let max = *endpoints().max().unwrap();
+
+        if layer == 0 {
+            self.layers[0].add(min, max);
+            return;
+        }
+
+        self.layers[layer].add(min.saturating_sub(1), max + 1);
+        for &e in endpoints() {
+            for r in self.layers[..layer].iter_mut() {
+                r.add(e.saturating_sub(1), e + 1);
+            }
+            self.via_rows.push(e);
+        }
+    }
+}
+
 #[derive(Copy, Clone, PartialEq, Debug)]
 pub enum ChannelState {
     Free,
@@ -79,6 +151,8 @@ pub struct WireConnection {
     pub from: usize,
     pub to: Vec<usize>,
     pub mode: ChannelOp,
+    // Layer the vertical part of the wire is placed on.
+    pub layer: usize,
 }
 
 #[derive(Debug)]
@@ -87,6 +161,85 @@ pub struct ChannelSubState {
     pub occupancy_map: bitmap::Bitmap<Vec<usize>, bitmap::OneBit>,
 }
 
+// Marks all rows that carry a net after the current step.
+fn occupancy_map(state: &ChannelLayout) -> bitmap::Bitmap<Vec<usize>, bitmap::OneBit> {
+    let mut bitmap =
+        bitmap::Bitmap::from_storage(state.len(), (), vec![0; (state.len() + 63) / 64]).unwrap();
+    for idx in state
+        .iter()
+        .enumerate()
+        .filter(|(_, v)| v.contains_net())
+        .map(|(k, _)| k)
+    {
+        bitmap.set(idx, 1);
+    }
+    bitmap
+}
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Router {
+    // Routes whole tasks and evicts nets that are in the way.
+    Eviction,
+    // Greedy column-by-column router with doglegs, see `greedy.rs`.
+    Greedy,
+    // Track assignment using constraint graphs, see `left_edge.rs`.
+    LeftEdge,
+    // Runs all of the above and picks the narrowest channel.
+    Best,
+    // Rips up and reroutes conflicting nets until the channel fits into the
+    // given number of steps, see `negotiated.rs`.
+    Negotiated(usize),
+}
+
+impl Router {
+    // Parses the router given on the command line, the negotiated router also
+    // needs the maximum channel width.
+    pub fn new(name: &str, max_width: Option<&str>) -> Result<Self, String> {
+        match name {
+            "negotiated" => max_width
+                .ok_or_else(|| "The negotiated router needs a maximum channel width".to_string())?
+                .parse::<usize>()
+                .map(Router::Negotiated)
+                .map_err(|_| "Invalid maximum channel width".to_string()),
+            name => Router::try_from(name).map_err(|_| format!("Unknown router {}", name)),
+        }
+    }
+
+    // Returns None if the channel does not fit into the maximum width of the
+    // negotiated router.
+    pub fn route(
+        self,
+        start: &ChannelLayout,
+        end: &ChannelLayout,
+        layers: usize,
+    ) -> Option<Vec<ChannelSubState>> {
+        match self {
+            Router::Eviction => Some(route_channel(start, end, layers)),
+            Router::Greedy => Some(route_channel_greedy(start, end, layers)),
+            Router::LeftEdge => Some(route_channel_left_edge(start, end, layers)),
+            Router::Best => [Router::Eviction, Router::Greedy, Router::LeftEdge]
+                .iter()
+                .filter_map(|router| router.route(start, end, layers))
+                .min_by_key(|steps| steps.len()),
+            Router::Negotiated(max_width) => route_channel_negotiated(start, end, max_width),
+        }
+    }
+}
+
+impl TryFrom<&str> for Router {
+    type Error = ();
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        match name {
+            "eviction" => Ok(Router::Eviction),
+            "greedy" => Ok(Router::Greedy),
+            "left_edge" => Ok(Router::LeftEdge),
+            "best" => Ok(Router::Best),
+            _ => Err(()),
+        }
+    }
+}
+
 #[derive(Debug)]
 struct Task {
     net: usize,
@@ -171,7 +324,12 @@ impl RouteTasks {
     }
 }
 
-pub fn route_channel(start: &ChannelLayout, end: &ChannelLayout) -> Vec<ChannelSubState> {
+// Routes the channel, placing the vertical wires on up to `layers` layers.
+pub fn route_channel(
+    start: &ChannelLayout,
+    end: &ChannelLayout,
+    layers: usize,
+) -> Vec<ChannelSubState> {
     let mut state = start.to_owned();
     // Expand the state to be at least end.len() wide.
     while state.len() < end.len() {
@@ -197,10 +355,11 @@ pub fn route_channel(start: &ChannelLayout, end: &ChannelLayout) -> Vec<ChannelS
     tasks.sort_by_key(|k| k.channel_width_required());
 
     let mut steps: Vec<ChannelSubState> = Vec::new();
+    let mut via_rows = Vec::new();
 
     loop {
         // Ranges of the channel that is currently occupied.
-        let mut ranges = Ranges::new();
+        let mut ranges = LayerRanges::new(layers, via_rows);
         // Instruction on how to connect pins in the current part of the channel.
         let mut wires = Vec::new();
         // To detect if we were unable to do anything due to blocked pins.
@@ -209,15 +368,13 @@ pub fn route_channel(start: &ChannelLayout, end: &ChannelLayout) -> Vec<ChannelS
         tasks = tasks
             .drain(0..tasks.len())
             .filter(|task| {
-                // Speed things up by only 'enforcing' 50% channel utilization.
-                if ranges.range_sum() > (cmp::max(state.len(), end.len()) / 2) {
-                    return true;
-                }
-
                 // Do we have the required part of the channel available?
-                if ranges.contains_range(&task.channel_range_required()) {
-                    return true;
-                }
+                // Speed things up by only 'enforcing' 50% channel utilization.
+                let max_range_sum = cmp::max(state.len(), end.len()) / 2;
+                let layer = match ranges.find_layer(task.from, &task.to, max_range_sum) {
+                    Some(layer) => layer,
+                    None => return true,
+                };
 
                 let blocking_pins = task.occupied_target_pins(&state);
                 if blocking_pins.is_empty() {
@@ -238,11 +395,10 @@ pub fn route_channel(start: &ChannelLayout, end: &ChannelLayout) -> Vec<ChannelS
                         } else {
                             ChannelOp::Move
                         },
+                        layer,
                     });
 
-                    let r = task.channel_range_required();
-                    // -1 here since .add() + channel_range_required() will do +1.
-                    ranges.add(r.start, r.end - 1);
+                    ranges.reserve(layer, task.from, &task.to);
 
                     for &to in &task.to {
                         state[to] = ChannelState::Net(task.net);
@@ -299,18 +455,14 @@ pub fn route_channel(start: &ChannelLayout, end: &ChannelLayout) -> Vec<ChannelS
                     let new_pos = *free_positions.last().unwrap();
 
                     // Check whether the space is actually available.
-                    let req_range = std::ops::Range {
-                        start: cmp::min(from, new_pos),
-                        end: cmp::max(from, new_pos) + 1,
-                    };
-
-                    if !ranges.contains_range(&req_range) {
+                    if let Some(layer) = ranges.find_layer(from, &[new_pos], std::usize::MAX) {
                         free_positions.pop();
-                        ranges.add(from, new_pos);
+                        ranges.reserve(layer, from, &[new_pos]);
                         wires.push(WireConnection {
                             from,
                             to: vec![new_pos],
                             mode: ChannelOp::Move,
+                            layer,
                         });
                         tasks[task_idx_to_evict].from = new_pos;
                         state[new_pos] = ChannelState::Net(tasks[task_idx_to_evict].net);
@@ -320,21 +472,10 @@ pub fn route_channel(start: &ChannelLayout, end: &ChannelLayout) -> Vec<ChannelS
             }
         }
 
-        let mut bitmap =
-            bitmap::Bitmap::from_storage(state.len(), (), vec![0; (state.len() + 63) / 64])
-                .unwrap();
-        for idx in state
-            .iter()
-            .enumerate()
-            .filter(|(_, v)| v.contains_net())
-            .map(|(k, _)| k)
-        {
-            bitmap.set(idx, 1);
-        }
-
+        via_rows = ranges.via_rows;
         steps.push(ChannelSubState {
             wires,
-            occupancy_map: bitmap,
+            occupancy_map: occupancy_map(&state),
         });
         if tasks.is_empty() {
             return steps;
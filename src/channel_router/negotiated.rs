@@ -0,0 +1,433 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Negotiated congestion channel router, following PathFinder.
+//
+// The channel is a fixed grid of `max_width` steps and one row per track, no
+// tracks are added. Every net is routed on its own (using dynamic programming
+// as wires only go to the right), ignoring the other nets apart from the cost
+// of the blocks they use. Afterwards, all blocks used by more than one net get
+// more expensive, and all nets are ripped up and rerouted until nothing
+// conflicts anymore.
+//
+// Every block of the grid has two resources:
+// - horizontal: the net is on this row after the step, or leaves it here,
+// - vertical: the vertical part of a wire in this step. As in `LayerRanges`,
+//   the block below a vertical wire is kept free as well.
+// All wires are placed on the first layer.
+
+use super::*;
+
+const MAX_ITERATIONS: usize = 64;
+// Give up once the overuse didn't drop for this many iterations.
+const STALLED_ITERATIONS: usize = 8;
+// Nets only use the rows between their source and targets, and this many rows
+// above and below.
+const SPAN_MARGIN: usize = 4;
+// Base cost of every wire, so that nets don't move around for no reason.
+const WIRE_COST: f64 = 2.0;
+// Prefer wires early in the channel, so that the empty steps at the end can be
+// removed.
+const STEP_COST: f64 = 0.01;
+const HISTORY_FACTOR: f64 = 1.0;
+const INITIAL_PRESENT_FACTOR: f64 = 0.5;
+const PRESENT_FACTOR_GROWTH: f64 = 1.5;
+
+struct Grid {
+    rows: usize,
+    // [step][row]
+    horizontal: Vec<Vec<usize>>,
+    vertical: Vec<Vec<usize>>,
+    horizontal_history: Vec<Vec<f64>>,
+    vertical_history: Vec<Vec<f64>>,
+    present_factor: f64,
+}
+
+impl Grid {
+    fn new(steps: usize, rows: usize) -> Self {
+        Self {
+            rows,
+            horizontal: vec![vec![0; rows]; steps],
+            vertical: vec![vec![0; rows]; steps],
+            horizontal_history: vec![vec![0.0; rows]; steps],
+            vertical_history: vec![vec![0.0; rows]; steps],
+            present_factor: INITIAL_PRESENT_FACTOR,
+        }
+    }
+
+    fn steps(&self) -> usize {
+        self.horizontal.len()
+    }
+
+    fn update(&mut self, usage: &Usage, delta: isize) {
+        for &(step, row) in &usage.horizontal {
+            self.horizontal[step][row] = (self.horizontal[step][row] as isize + delta) as usize;
+        }
+        for &(step, row) in &usage.vertical {
+            self.vertical[step][row] = (self.vertical[step][row] as isize + delta) as usize;
+        }
+    }
+
+    fn cost(&self, occupancy: usize, history: f64) -> f64 {
+        (1.0 + history) * (1.0 + self.present_factor * occupancy as f64)
+    }
+
+    // Adds the overused blocks to the history, returns the total overuse.
+    fn update_history(&mut self) -> usize {
+        let mut overused = 0;
+        for step in 0..self.steps() {
+            for row in 0..self.rows {
+                if self.horizontal[step][row] > 1 {
+                    self.horizontal_history[step][row] +=
+                        HISTORY_FACTOR * (self.horizontal[step][row] - 1) as f64;
+                    overused += self.horizontal[step][row] - 1;
+                }
+                if self.vertical[step][row] > 1 {
+                    self.vertical_history[step][row] +=
+                        HISTORY_FACTOR * (self.vertical[step][row] - 1) as f64;
+                    overused += self.vertical[step][row] - 1;
+                }
+            }
+        }
+        self.present_factor *= PRESENT_FACTOR_GROWTH;
+        overused
+    }
+}
+
+#[derive(Default)]
+struct Usage {
+    horizontal: Vec<(usize, usize)>,
+    vertical: Vec<(usize, usize)>,
+}
+
+struct Net {
+    from: usize,
+    to: Vec<usize>,
+    // Whether the net has to stay on its initial row.
+    stays: bool,
+    wires: Vec<(usize, WireConnection)>,
+    usage: Usage,
+}
+
+impl Net {
+    // Routes the net with the current costs of the grid. This takes
+    // O(steps * span^2), where span is the number of rows the net may use.
+    fn route(&mut self, grid: &Grid) {
+        let steps = grid.steps();
+        let rows = grid.rows;
+        let t_min = *self.to.iter().min().unwrap();
+        let t_max = *self.to.iter().max().unwrap();
+        let first_row = cmp::min(self.from, t_min).saturating_sub(SPAN_MARGIN);
+        let last_row = cmp::min(cmp::max(self.from, t_max) + SPAN_MARGIN, rows - 1);
+        let span = first_row..last_row + 1;
+        let h_cost = |step: usize, row: usize| {
+            grid.cost(
+                grid.horizontal[step][row],
+                grid.horizontal_history[step][row],
+            )
+        };
+        // Cost of the vertical blocks of each step, summed up from the top.
+        let v_prefix = (0..steps)
+            .map(|step| {
+                let mut sum = vec![0.0; rows + 1];
+                for row in 0..rows {
+                    sum[row + 1] = sum[row]
+                        + grid.cost(grid.vertical[step][row], grid.vertical_history[step][row]);
+                }
+                sum
+            })
+            .collect::<Vec<_>>();
+        let wire_cost = |step: usize, min: usize, max: usize| {
+            let max = cmp::min(max + 1, rows - 1);
+            WIRE_COST + STEP_COST * step as f64 + v_prefix[step][max + 1] - v_prefix[step][min]
+        };
+        // Cost of staying on a target row from the given step until the end.
+        let h_suffix = |row: usize| {
+            let mut suffix = vec![0.0; steps + 1];
+            for step in (0..steps).rev() {
+                suffix[step] = suffix[step + 1] + h_cost(step, row);
+            }
+            suffix
+        };
+        let h_suffix = self.to.iter().map(|&t| h_suffix(t)).collect::<Vec<_>>();
+
+        let fanout_cost = |step: usize, row: usize| {
+            let mut cost = wire_cost(step, cmp::min(row, t_min), cmp::max(row, t_max));
+            if !self.to.contains(&row) {
+                cost += h_cost(step, row);
+            }
+            cost + h_suffix.iter().map(|suffix| suffix[step]).sum::<f64>()
+        };
+
+        if self.stays {
+            // Copy to the other targets in the cheapest step.
+            let step = (0..steps)
+                .min_by(|&a, &b| {
+                    fanout_cost(a, self.from)
+                        .partial_cmp(&fanout_cost(b, self.from))
+                        .unwrap()
+                })
+                .unwrap();
+            self.set_route(&[], step, self.from, grid);
+            return;
+        }
+
+        // cost[step][row - first_row]: being on `row` after `step` (before the
+        // fanout).
+        let mut cost = vec![vec![f64::INFINITY; span.len()]; steps];
+        let mut previous = vec![vec![0; span.len()]; steps];
+        let mut best = (f64::INFINITY, 0, 0);
+        for step in 0..steps {
+            let before = if step == 0 {
+                let mut before = vec![f64::INFINITY; span.len()];
+                before[self.from - first_row] = 0.0;
+                before
+            } else {
+                cost[step - 1].clone()
+            };
+            let reachable = span
+                .clone()
+                .filter(|&row| before[row - first_row].is_finite())
+                .collect::<Vec<_>>();
+
+            for &row in &reachable {
+                let c = before[row - first_row] + fanout_cost(step, row);
+                if c < best.0 {
+                    best = (c, step, row);
+                }
+            }
+
+            for next in span.clone() {
+                for &row in &reachable {
+                    let c = if row == next {
+                        before[row - first_row] + h_cost(step, row)
+                    } else {
+                        before[row - first_row]
+                            + wire_cost(step, cmp::min(row, next), cmp::max(row, next))
+                            + h_cost(step, row)
+                            + h_cost(step, next)
+                    };
+                    if c < cost[step][next - first_row] {
+                        cost[step][next - first_row] = c;
+                        previous[step][next - first_row] = row;
+                    }
+                }
+            }
+        }
+
+        let (_, fanout_step, fanout_row) = best;
+        let mut path = vec![0; fanout_step];
+        let mut row = fanout_row;
+        for step in (0..fanout_step).rev() {
+            path[step] = row;
+            row = previous[step][row - first_row];
+        }
+        self.set_route(&path, fanout_step, fanout_row, grid);
+    }
+
+    // Stores the wires and used blocks of a route: The net is on `path[step]`
+    // after each step and connected to its targets from `row` in `fanout_step`.
+    fn set_route(&mut self, path: &[usize], fanout_step: usize, row: usize, grid: &Grid) {
+        let mut wires = Vec::new();
+        let mut usage = Usage::default();
+
+        let mut add_wire = |step: usize, from: usize, to: Vec<usize>, mode: ChannelOp| {
+            let min = *to.iter().chain(std::iter::once(&from)).min().unwrap();
+            let max = *to.iter().chain(std::iter::once(&from)).max().unwrap();
+            for r in min..=cmp::min(max + 1, grid.rows - 1) {
+                usage.vertical.push((step, r));
+            }
+            if mode == ChannelOp::Move {
+                usage.horizontal.push((step, from));
+            }
+            wires.push((
+                step,
+                WireConnection {
+                    from,
+                    to,
+                    mode,
+                    layer: 0,
+                },
+            ));
+        };
+
+        let mut current = self.from;
+        for (step, &r) in path.iter().enumerate() {
+            if r != current {
+                add_wire(step, current, vec![r], ChannelOp::Move);
+            }
+            current = r;
+        }
+        let to = self
+            .to
+            .iter()
+            .copied()
+            .filter(|&t| t != row)
+            .collect::<Vec<_>>();
+        if !to.is_empty() {
+            let mode = if self.to.contains(&row) {
+                ChannelOp::Copy
+            } else {
+                ChannelOp::Move
+            };
+            add_wire(fanout_step, row, to, mode);
+        }
+
+        for (step, &r) in path.iter().enumerate() {
+            usage.horizontal.push((step, r));
+        }
+        for &r in &self.to {
+            let first = if r == self.from && self.stays {
+                0
+            } else {
+                fanout_step
+            };
+            for step in first..grid.steps() {
+                usage.horizontal.push((step, r));
+            }
+        }
+
+        self.wires = wires;
+        self.usage = usage;
+    }
+}
+
+// Routes the channel in at most `max_width` steps, returns None if it does not
+// fit.
+pub fn route_channel_negotiated(
+    start: &ChannelLayout,
+    end: &ChannelLayout,
+    max_width: usize,
+) -> Option<Vec<ChannelSubState>> {
+    if max_width == 0 {
+        return None;
+    }
+
+    let mut state = start.to_owned();
+    // Expand the state to be at least end.len() wide.
+    while state.len() < end.len() {
+        state.push(ChannelState::Free);
+    }
+
+    let mut nets = Vec::new();
+    let mut net_ids = end
+        .iter()
+        .filter_map(|v| match v {
+            ChannelState::Net(net) => Some(*net),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    net_ids.sort();
+    net_ids.dedup();
+    for net in net_ids {
+        let to = (0..end.len())
+            .filter(|&idx| end[idx] == ChannelState::Net(net))
+            .collect::<Vec<_>>();
+        let min = *to.iter().min().unwrap();
+        let max = *to.iter().max().unwrap();
+        let from = (0..state.len())
+            .filter(|&idx| state[idx] == ChannelState::Net(net))
+            .min_by_key(|&idx| cmp::max(max, idx) - cmp::min(min, idx))
+            .unwrap_or_else(|| panic!("Required net {} not found", net));
+        nets.push(Net {
+            from,
+            stays: to.contains(&from),
+            to,
+            wires: Vec::new(),
+            usage: Usage::default(),
+        });
+    }
+
+    // Tracks that are not required anymore end right away.
+    for (idx, track) in state.iter_mut().enumerate() {
+        if track.contains_net() && nets.iter().all(|n| n.from != idx) {
+            *track = ChannelState::Free;
+        }
+    }
+
+    let mut grid = Grid::new(max_width, state.len());
+    let mut routed = false;
+    let mut lowest = (usize::MAX, 0);
+    for iteration in 0..MAX_ITERATIONS {
+        for net in nets.iter_mut() {
+            grid.update(&net.usage, -1);
+            net.route(&grid);
+            grid.update(&net.usage, 1);
+        }
+        let overused = grid.update_history();
+        if overused == 0 {
+            routed = true;
+            break;
+        }
+        if overused < lowest.0 {
+            lowest = (overused, iteration);
+        } else if iteration - lowest.1 >= STALLED_ITERATIONS {
+            break;
+        }
+    }
+    if !routed {
+        return None;
+    }
+
+    // Steps without any wires can be left out.
+    let mut steps: Vec<ChannelSubState> = Vec::new();
+    for step in 0..max_width {
+        let wires = nets
+            .iter()
+            .flat_map(|n| n.wires.iter())
+            .filter(|(s, _)| *s == step)
+            .map(|(_, wire)| wire.clone())
+            .collect::<Vec<_>>();
+        if wires.is_empty() {
+            continue;
+        }
+
+        for wire in &wires {
+            let net = state[wire.from];
+            if wire.mode == ChannelOp::Move {
+                state[wire.from] = ChannelState::Free;
+            }
+            for &to in &wire.to {
+                state[to] = net;
+            }
+        }
+        steps.push(ChannelSubState {
+            wires,
+            occupancy_map: occupancy_map(&state),
+        });
+    }
+    if steps.is_empty() {
+        steps.push(ChannelSubState {
+            wires: Vec::new(),
+            occupancy_map: occupancy_map(&state),
+        });
+    }
+    Some(steps)
+}
+
+// The smallest number of steps the channel fits into, for reporting channels
+// that don't fit. Searches below the width the other routers need (assuming
+// that a channel fitting into some width also fits into any larger one).
+pub fn min_channel_width(start: &ChannelLayout, end: &ChannelLayout) -> usize {
+    let (mut low, mut high) = (0, Router::Best.route(start, end, 1).unwrap().len());
+    while high - low > 1 {
+        let width = (low + high) / 2;
+        if route_channel_negotiated(start, end, width).is_some() {
+            high = width;
+        } else {
+            low = width;
+        }
+    }
+    high
+}
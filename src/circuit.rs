@@ -100,7 +100,7 @@ impl std::cmp::Ord for Port {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct Circuit {
     pub basic_circuit: BasicCircuitYada,
 
@@ -138,6 +138,15 @@ impl Circuit {
         }
     }
 
+    pub fn new_feedback_pin(net: Port) -> Self {
+        Self {
+            basic_circuit: BasicCircuitYada::feedback(),
+            inputs: Vec::new(),
+            outputs: vec![net],
+            position: None,
+        }
+    }
+
     pub fn width(&self) -> u32 {
         self.basic_circuit.width() as u32
     }
@@ -193,6 +202,22 @@ impl Circuit {
                 );
             }
         }
+
+        // Hard macros can also use the layers above.
+        if let BasicCircuitYada::HardMacro(hard_macro) = self.basic_circuit {
+            for (layer, layout) in hard_macro.layers().iter().enumerate().skip(1) {
+                for x in 0..w {
+                    for y in 0..h {
+                        canvas.set_layer(
+                            p.0 as usize + x,
+                            p.1 as usize + y,
+                            layer,
+                            layout[y * w + x],
+                        );
+                    }
+                }
+            }
+        }
     }
 
     pub fn place(&mut self, position: Position2D) {
@@ -219,15 +244,13 @@ impl Circuit {
     }
 }
 
-impl TryFrom<&YosysJsonCell> for Circuit {
-    type Error = ();
-    fn try_from(cell: &YosysJsonCell) -> Result<Self, Self::Error> {
+impl Circuit {
+    // Connects the given cell, e.g. one from a cell library.
+    pub fn from_cell(basic_circuit: BasicCircuitYada, cell: &YosysJsonCell) -> Self {
         for i in cell.connections.iter() {
             assert!(i.1.len() == 1);
         }
 
-        let basic_circuit = BasicCircuitYada::try_from(&*cell.cell_type)?;
-
         // Check we only have 1 bit inputs.
         if cell.connections.iter().any(|(_, e)| e.len() != 1) {
             panic!(
@@ -261,12 +284,35 @@ impl TryFrom<&YosysJsonCell> for Circuit {
             panic!("Missed some connections on {:?}", cell);
         }
 
-        Ok(Self {
-            basic_circuit: BasicCircuitYada::try_from(&*cell.cell_type).unwrap(),
+        Self {
+            basic_circuit,
             inputs,
             outputs,
             position: None,
-        })
+        }
+    }
+}
+
+impl TryFrom<&YosysJsonCell> for Circuit {
+    type Error = ();
+    fn try_from(cell: &YosysJsonCell) -> Result<Self, Self::Error> {
+        if let Some(alias) = CellAlias::get(&cell.cell_type) {
+            // Only `$fa` has a width, yosys' gate cells are always 1 bit wide.
+            if cell.connections.values().any(|bits| bits.len() != 1) {
+                panic!(
+                    "Only 1 bit wide {} cells are supported, split them with `techmap` first: {:?}",
+                    cell.cell_type, cell
+                );
+            }
+            let mut cell = cell.clone();
+            cell.connections = std::mem::take(&mut cell.connections)
+                .into_iter()
+                .map(|(port, bits)| (alias.port_name(&port).to_string(), bits))
+                .collect();
+            return Ok(Self::from_cell(alias.cell, &cell));
+        }
+        let basic_circuit = BasicCircuitYada::try_from(&*cell.cell_type)?;
+        Ok(Self::from_cell(basic_circuit, cell))
     }
 }
 
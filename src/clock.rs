@@ -0,0 +1,250 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Clock distribution for sequential gates.
+//
+// Clock nets are neither forwarded nor routed through the channels. Instead,
+// every clock net drops down from its source to a spine below the circuit,
+// which runs to a reserved tap column in front of the last gate column. The
+// tap column connects to the clock inputs of all sequential gates:
+//
+//    src ─┐                ┌─ DFF
+//         │                ├─ DFF
+//         └────────────────┘
+//
+// Spines are nested (the one with the rightmost source is at the top), so
+// that they never cross each other. The spine consists of wires only, so
+// every sequential gate on the same clock net sees the clock at the same
+// time.
+
+use crate::canvas::{BlockType, Canvas, CornerOrientation, TRotation};
+use crate::circuit::Circuit;
+use crate::gate::BasicCircuitDetails;
+use std::collections::{HashMap, HashSet};
+
+pub struct ClockSource {
+    pub net: usize,
+    // Block next to the output pin that drives the clock net.
+    pub source: (usize, usize),
+    // Column the clock net drops down to the spine in.
+    pub drop_x: usize,
+}
+
+// Nets that only drive clock inputs of sequential gates. Nets that drive other
+// gates as well are routed like any other net.
+pub fn find_clock_nets(gate_hierarchy: &[Vec<Circuit>]) -> HashSet<usize> {
+    let mut clock_nets = HashSet::new();
+    let mut other_nets = HashSet::new();
+    for c in gate_hierarchy.iter().flatten() {
+        for (idx, i) in c.inputs.iter().enumerate() {
+            if let Some(net) = i.connection.get_net() {
+                if c.basic_circuit.clock_input() == Some(idx) {
+                    clock_nets.insert(net);
+                } else {
+                    other_nets.insert(net);
+                }
+            }
+        }
+    }
+
+    let mut shared = clock_nets.intersection(&other_nets).collect::<Vec<_>>();
+    shared.sort();
+    for net in shared {
+        println!(
+            "[!] Clock net {} drives other gates as well, routing it through the channels",
+            net
+        );
+    }
+    clock_nets.difference(&other_nets).copied().collect()
+}
+
+struct Delays<'a> {
+    producers: HashMap<usize, &'a Circuit>,
+    // Sequential gates by their output nets, for feedback pins.
+    sequential: HashMap<usize, &'a Circuit>,
+    memo: HashMap<usize, usize>,
+    visiting: HashSet<usize>,
+}
+
+impl<'a> Delays<'a> {
+    // Number of gates between the inputs of the circuit and the given net.
+    fn arrival(&mut self, net: usize) -> usize {
+        if let Some(&delay) = self.memo.get(&net) {
+            return delay;
+        }
+        let producer = match self.producers.get(&net) {
+            Some(&producer) => producer,
+            None => return 0,
+        };
+
+        let delay = if producer.basic_circuit.is_feedback() {
+            // Derived clocks (e.g. ripple counters) are driven by another
+            // sequential gate, loops are cut off.
+            match self.sequential.get(&net) {
+                Some(&gate) if self.visiting.insert(net) => {
+                    let clock = gate.basic_circuit.clock_input().unwrap();
+                    let delay = match gate.inputs[clock].connection.get_net() {
+                        Some(clock_net) => self.arrival(clock_net),
+                        None => 0,
+                    };
+                    self.visiting.remove(&net);
+                    delay + gate.basic_circuit.delay()
+                }
+                _ => 0,
+            }
+        } else {
+            producer
+                .inputs
+                .iter()
+                .filter_map(|i| i.connection.get_net())
+                .map(|net| self.arrival(net))
+                .max()
+                .unwrap_or(0)
+                + producer.basic_circuit.delay()
+        };
+        self.memo.insert(net, delay);
+        delay
+    }
+}
+
+// Prints the number of gates between the inputs of the circuit and the clock
+// input of every sequential gate.
+pub fn report_clock_delays(gate_hierarchy: &[Vec<Circuit>]) {
+    let gates = gate_hierarchy.iter().flatten().collect::<Vec<_>>();
+    let mut delays = Delays {
+        producers: HashMap::new(),
+        sequential: HashMap::new(),
+        memo: HashMap::new(),
+        visiting: HashSet::new(),
+    };
+    for &c in gates.iter() {
+        for net in c.outputs.iter().filter_map(|o| o.connection.get_net()) {
+            if c.basic_circuit.is_sequential() {
+                delays.sequential.insert(net, c);
+            } else {
+                delays.producers.insert(net, c);
+            }
+        }
+    }
+
+    let mut report = Vec::new();
+    for &c in gates.iter() {
+        if let Some(clock) = c.basic_circuit.clock_input() {
+            let output = c.outputs[0].connection.get_net().unwrap();
+            match c.inputs[clock].connection.get_net() {
+                Some(net) => report.push((output, net, delays.arrival(net))),
+                None => println!("[!] Flip-flop driving net {} has a constant clock", output),
+            }
+        }
+    }
+    if report.is_empty() {
+        return;
+    }
+
+    println!("[*] Clock delays");
+    report.sort();
+    for (output, net, delay) in report.iter() {
+        println!(
+            " [+] Flip-flop driving net {}: clock net {}, {} gate delay(s)",
+            output, net, delay
+        );
+    }
+    let min = report.iter().map(|r| r.2).min().unwrap();
+    let max = report.iter().map(|r| r.2).max().unwrap();
+    println!(" [+] Clock skew: {} gate delay(s)", max - min);
+}
+
+// Draws the spines of all clock nets. `tap_x` is the first of the reserved
+// columns in front of the last gate column, one for each clock net.
+pub fn draw_clock_spines(
+    canvas: &mut Canvas,
+    mut sources: Vec<ClockSource>,
+    tap_x: usize,
+    gate_hierarchy: &[Vec<Circuit>],
+) {
+    if sources.is_empty() {
+        return;
+    }
+    sources.sort_by_key(|s| std::cmp::Reverse(s.drop_x));
+
+    let n = sources.len();
+    let bottom = canvas.dimensions().1;
+    let last_tap_x = tap_x + n - 1;
+    for (idx, source) in sources.iter().enumerate() {
+        let spine_y = bottom + idx;
+        let column_x = tap_x + idx;
+
+        let mut taps = gate_hierarchy
+            .last()
+            .unwrap()
+            .iter()
+            .filter_map(|c| {
+                c.basic_circuit
+                    .clock_input()
+                    .map(|clock| &c.inputs[clock])
+                    .filter(|i| i.connection.get_net() == Some(source.net))
+                    .map(|i| i.position.unwrap().1 as usize)
+            })
+            .collect::<Vec<_>>();
+        taps.sort();
+
+        // Down from the source to the spine.
+        let (x, y) = source.source;
+        for x in x..source.drop_x {
+            canvas.set_wire(x, y, BlockType::WireH);
+        }
+        canvas.set(
+            source.drop_x,
+            y,
+            BlockType::WireCorner(CornerOrientation::LeftDown),
+        );
+        for y in y + 1..spine_y {
+            canvas.set_wire(source.drop_x, y, BlockType::WireV);
+        }
+        canvas.set(
+            source.drop_x,
+            spine_y,
+            BlockType::WireCorner(CornerOrientation::UpRight),
+        );
+
+        // Along the spine and up the tap column.
+        for x in source.drop_x + 1..column_x {
+            canvas.set_wire(x, spine_y, BlockType::WireH);
+        }
+        canvas.set(
+            column_x,
+            spine_y,
+            BlockType::WireCorner(CornerOrientation::LeftUp),
+        );
+        for y in taps[0] + 1..spine_y {
+            if taps.contains(&y) {
+                canvas.set(column_x, y, BlockType::WireT(TRotation::RightUpDown));
+            } else {
+                canvas.set_wire(column_x, y, BlockType::WireV);
+            }
+        }
+        canvas.set(
+            column_x,
+            taps[0],
+            BlockType::WireCorner(CornerOrientation::DownRight),
+        );
+
+        // Over to the clock inputs.
+        for &y in taps.iter() {
+            for x in column_x + 1..=last_tap_x {
+                canvas.set_wire(x, y, BlockType::WireH);
+            }
+        }
+    }
+}
@@ -0,0 +1,122 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Routes the outputs of sequential gates back to the beginning of the circuit.
+//
+// Sequential gates are placed in the last column, while their outputs are
+// provided by feedback pins in the first column. Every feedback net leaves
+// the sequential gate to the right, goes down below the circuit, back to the
+// left along a bus and up again to its feedback pin:
+//
+//   ┌─ pin ── ... ── DFF ─┐
+//   │                     │
+//   └─────────────────────┘
+//
+// The nets are nested, so that the only wire crossings are between the wires
+// to the right of the circuit and these never have a corner on another wire.
+// The feedback pin that is nearest to the top gets the outermost path.
+
+use crate::canvas::{BlockType, Canvas, CornerOrientation};
+use crate::circuit::Circuit;
+use std::collections::HashMap;
+
+// Expects the first column to be placed `n` blocks to the right, where `n` is
+// the number of feedback pins.
+pub fn route_feedback_nets(canvas: &mut Canvas, gate_hierarchy: &[Vec<Circuit>]) {
+    let mut pins = gate_hierarchy[0]
+        .iter()
+        .filter(|c| c.basic_circuit.is_feedback())
+        .map(|c| {
+            let position = c.position.unwrap();
+            (
+                c.outputs[0].connection.get_net().unwrap(),
+                (position.0 as usize, position.1 as usize),
+            )
+        })
+        .collect::<Vec<_>>();
+    if pins.is_empty() {
+        return;
+    }
+    pins.sort_by_key(|&(_, (_, y))| y);
+
+    // All sequential gates share the same column, their outputs are extended
+    // to the widest gate of the column.
+    let last_column = gate_hierarchy.last().unwrap();
+    let right = last_column
+        .iter()
+        .map(|c| (c.position.unwrap().0 + c.width()) as usize)
+        .max()
+        .unwrap();
+    let sources = last_column
+        .iter()
+        .filter(|c| c.basic_circuit.is_sequential())
+        .flat_map(|c| &c.outputs)
+        .filter_map(|o| {
+            o.connection
+                .get_net()
+                .map(|net| (net, o.position.unwrap().1 as usize))
+        })
+        .collect::<HashMap<_, _>>();
+
+    let n = pins.len();
+    let bottom = canvas.dimensions().1;
+    println!("[*] Routing {} feedback nets", n);
+    for (idx, &(net, pin)) in pins.iter().enumerate() {
+        let source_y = sources[&net];
+        let left_x = idx;
+        let right_x = right + n - idx;
+        let bus_y = bottom + n - 1 - idx;
+
+        // From the sequential gate down to the bus.
+        for x in right..right_x {
+            canvas.set_wire(x, source_y, BlockType::WireH);
+        }
+        canvas.set(
+            right_x,
+            source_y,
+            BlockType::WireCorner(CornerOrientation::LeftDown),
+        );
+        for y in source_y + 1..bus_y {
+            canvas.set_wire(right_x, y, BlockType::WireV);
+        }
+        canvas.set(
+            right_x,
+            bus_y,
+            BlockType::WireCorner(CornerOrientation::LeftUp),
+        );
+
+        // Along the bus to the left.
+        for x in left_x + 1..right_x {
+            canvas.set_wire(x, bus_y, BlockType::WireH);
+        }
+
+        // Up to the feedback pin.
+        canvas.set(
+            left_x,
+            bus_y,
+            BlockType::WireCorner(CornerOrientation::UpRight),
+        );
+        for y in pin.1 + 1..bus_y {
+            canvas.set_wire(left_x, y, BlockType::WireV);
+        }
+        canvas.set(
+            left_x,
+            pin.1,
+            BlockType::WireCorner(CornerOrientation::DownRight),
+        );
+        for x in left_x + 1..pin.0 {
+            canvas.set_wire(x, pin.1, BlockType::WireH);
+        }
+    }
+}
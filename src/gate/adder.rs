@@ -0,0 +1,185 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::gate::{BasicCircuitDetails, MeseconsGate};
+use crate::{BlockType, CornerOrientation, TRotation};
+
+// S = A ^ B, CO = A & B. B feeds both gates from the middle row.
+#[derive(Copy, Clone, Debug)]
+pub struct HalfAdder;
+
+impl BasicCircuitDetails for HalfAdder {
+    fn yosys_id(&self) -> &str {
+        "HA"
+    }
+
+    fn get_layout(&self) -> &[BlockType] {
+        use BlockType::*;
+        use CornerOrientation::*;
+        &[
+            WireT(TRotation::LeftRightDown),
+            WireCorner(LeftDown),
+            WireV,
+            Gate(MeseconsGate::Xor),
+            WireCrossing,
+            WireT(TRotation::LeftUpDown),
+            WireV,
+            Gate(MeseconsGate::And),
+            WireCorner(UpRight),
+            WireCorner(LeftUp),
+        ]
+    }
+
+    fn width(&self) -> usize {
+        2
+    }
+    fn height(&self) -> usize {
+        5
+    }
+    // A -> Xor -> S
+    fn delay(&self) -> usize {
+        1
+    }
+    fn can_swap_input(&self) -> bool {
+        true
+    }
+
+    fn input_names(&self) -> &[&str] {
+        &["A", "B"]
+    }
+
+    fn input_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 0,
+            1 => 2,
+            _ => unreachable!(),
+        }
+    }
+
+    fn output_names(&self) -> &[&str] {
+        &["S", "CO"]
+    }
+
+    fn output_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 1,
+            1 => 3,
+            _ => unreachable!(),
+        }
+    }
+}
+
+// Two half adders, the carries of both are combined by an Or gate.
+#[derive(Copy, Clone, Debug)]
+pub struct FullAdder;
+
+impl BasicCircuitDetails for FullAdder {
+    fn yosys_id(&self) -> &str {
+        "FA"
+    }
+
+    fn get_layout(&self) -> &[BlockType] {
+        use BlockType::*;
+        use CornerOrientation::*;
+        &[
+            WireT(TRotation::LeftRightDown),
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Gate(MeseconsGate::Xor),
+            WireH,
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            WireCrossing,
+            WireT(TRotation::LeftUpDown),
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::Xor),
+            WireH,
+            WireV,
+            Gate(MeseconsGate::And),
+            WireCorner(LeftDown),
+            WireV,
+            Air,
+            WireV,
+            Air,
+            WireCorner(UpRight),
+            WireCorner(LeftUp),
+            WireV,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCrossing,
+            WireCorner(LeftDown),
+            WireH,
+            WireH,
+            WireCrossing,
+            WireT(TRotation::LeftRightUp),
+            WireH,
+            WireCorner(LeftUp),
+            Gate(MeseconsGate::Or),
+            Air,
+            Air,
+            WireCorner(UpRight),
+            WireH,
+            WireH,
+            WireH,
+            WireCorner(LeftUp),
+        ]
+    }
+
+    fn width(&self) -> usize {
+        7
+    }
+    fn height(&self) -> usize {
+        7
+    }
+    // A -> Xor -> And -> Or -> CO
+    fn delay(&self) -> usize {
+        3
+    }
+    fn can_swap_input(&self) -> bool {
+        true
+    }
+
+    fn input_names(&self) -> &[&str] {
+        &["A", "B", "CI"]
+    }
+
+    fn input_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 0,
+            1 => 2,
+            2 => 5,
+            _ => unreachable!(),
+        }
+    }
+
+    fn output_names(&self) -> &[&str] {
+        &["S", "CO"]
+    }
+
+    fn output_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 2,
+            1 => 5,
+            _ => unreachable!(),
+        }
+    }
+}
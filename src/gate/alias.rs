@@ -0,0 +1,82 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// yosys' internal gate cells (`$_AND_`, `$_DFF_P_`, ...), as left by `synth`
+// when the design isn't mapped with `abc`, are placed as the cell with the same
+// function.
+
+use crate::gate::adder::FullAdder;
+use crate::gate::dff::{DffN, DffP, DffPN0, DffPP0};
+use crate::gate::latch::{DLatchP, SrLatch};
+use crate::gate::*;
+
+pub struct CellAlias {
+    yosys_id: &'static str,
+    pub cell: BasicCircuitYada,
+    // (yosys port, cell port) for ports that are named differently.
+    ports: &'static [(&'static str, &'static str)],
+}
+
+const fn alias(yosys_id: &'static str, cell: BasicCircuitYada) -> CellAlias {
+    CellAlias {
+        yosys_id,
+        cell,
+        ports: &[],
+    }
+}
+
+static ALIASES: &[CellAlias] = &[
+    alias("$_BUF_", BasicCircuitYada::BufGate(BufGate)),
+    alias("$_NOT_", BasicCircuitYada::Not(NotGate)),
+    alias("$_AND_", BasicCircuitYada::And(AndGate)),
+    alias("$_NAND_", BasicCircuitYada::Nand(NandGate)),
+    alias("$_OR_", BasicCircuitYada::Or(OrGate)),
+    alias("$_NOR_", BasicCircuitYada::Nor(NorGate)),
+    alias("$_XOR_", BasicCircuitYada::Xor(XorGate)),
+    alias("$_XNOR_", BasicCircuitYada::Xnor(XnorGate)),
+    alias("$_ANDNOT_", BasicCircuitYada::AndNot(AndNotGate)),
+    alias("$_ORNOT_", BasicCircuitYada::OrNot(OrNotGate)),
+    alias("$_AOI3_", BasicCircuitYada::Aoi21(Aoi21Gate)),
+    alias("$_OAI3_", BasicCircuitYada::Oai21(Oai21Gate)),
+    alias("$_AOI4_", BasicCircuitYada::Aoi22(Aoi22Gate)),
+    alias("$_OAI4_", BasicCircuitYada::Oai22(Oai22Gate)),
+    alias("$_MUX_", BasicCircuitYada::Mux(MuxGate)),
+    alias("$_DFF_P_", BasicCircuitYada::DffP(DffP)),
+    alias("$_DFF_N_", BasicCircuitYada::DffN(DffN)),
+    alias("$_DFF_PP0_", BasicCircuitYada::DffPP0(DffPP0)),
+    alias("$_DFF_PN0_", BasicCircuitYada::DffPN0(DffPN0)),
+    alias("$_DLATCH_P_", BasicCircuitYada::DLatchP(DLatchP)),
+    alias("$_SR_PP_", BasicCircuitYada::SrLatch(SrLatch)),
+    // Single bit full adders, e.g. from `extract_fa`.
+    CellAlias {
+        yosys_id: "$fa",
+        cell: BasicCircuitYada::FullAdder(FullAdder),
+        ports: &[("C", "CI"), ("X", "CO"), ("Y", "S")],
+    },
+];
+
+impl CellAlias {
+    pub fn get(cell_type: &str) -> Option<&'static Self> {
+        ALIASES.iter().find(|a| a.yosys_id == cell_type)
+    }
+
+    // The name of the cell's port for the given port of the yosys cell.
+    pub fn port_name<'a>(&self, port: &'a str) -> &'a str {
+        self.ports
+            .iter()
+            .find(|(from, _)| *from == port)
+            .map(|(_, to)| *to)
+            .unwrap_or(port)
+    }
+}
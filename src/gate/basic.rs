@@ -53,6 +53,13 @@ macro_rules! TrivialGate {
             fn height(&self) -> usize {
                 3
             }
+            fn delay(&self) -> usize {
+                if $inverted {
+                    2
+                } else {
+                    1
+                }
+            }
             fn can_swap_input(&self) -> bool {
                 true
             }
@@ -113,6 +120,14 @@ macro_rules! SingleFieldGate {
             fn height(&self) -> usize {
                 1
             }
+            fn delay(&self) -> usize {
+                use BlockType::*;
+                // Everything apart from inverters is just a wire.
+                match $field {
+                    Gate(MeseconsGate::Not) => 1,
+                    _ => 0,
+                }
+            }
             fn can_swap_input(&self) -> bool {
                 true
             }
@@ -151,6 +166,8 @@ SingleFieldGate!(NotGate, "NOT", Gate(MeseconsGate::Not), true);
 SingleFieldGate!(InputGate, "INVALID", Gate(MeseconsGate::Input), false);
 SingleFieldGate!(OutputGate, "INVALID", Gate(MeseconsGate::Output), true);
 SingleFieldGate!(ForwardGate, "INVALID", Gate(MeseconsGate::Forward), true);
+// Source of a net that is fed back from the output of a sequential gate.
+SingleFieldGate!(FeedbackGate, "INVALID", WireH, false);
 
 macro_rules! SthNotGate {
     ($gatename:ident, $yosys_id:literal, $basic_gate:expr) => {
@@ -179,6 +196,9 @@ macro_rules! SthNotGate {
             fn height(&self) -> usize {
                 3
             }
+            fn delay(&self) -> usize {
+                2
+            }
             fn can_swap_input(&self) -> bool {
                 false
             }
@@ -211,3 +231,142 @@ macro_rules! SthNotGate {
 
 SthNotGate!(AndNotGate, "ANDNOT", MeseconsGate::And);
 SthNotGate!(OrNotGate, "ORNOT", MeseconsGate::Or);
+
+// (A op1 B) op2 C, e.g. AND-OR-INVERT with `And` and `Nor`.
+macro_rules! ComplexGate21 {
+    ($gatename:ident, $yosys_id:literal, $first_gate:expr, $second_gate:expr) => {
+        #[derive(Copy, Clone, Debug)]
+        pub struct $gatename;
+        impl BasicCircuitDetails for $gatename {
+            fn yosys_id(&self) -> &str {
+                $yosys_id
+            }
+
+            fn get_layout(&self) -> &[BlockType] {
+                use BlockType::*;
+                &[
+                    WireCorner(CornerOrientation::LeftDown),
+                    Air,
+                    Gate($first_gate),
+                    WireCorner(CornerOrientation::LeftDown),
+                    WireCorner(CornerOrientation::LeftUp),
+                    Gate($second_gate),
+                    WireH,
+                    WireCorner(CornerOrientation::LeftUp),
+                ]
+            }
+
+            fn width(&self) -> usize {
+                2
+            }
+            fn height(&self) -> usize {
+                4
+            }
+            fn delay(&self) -> usize {
+                2
+            }
+            fn can_swap_input(&self) -> bool {
+                true
+            }
+
+            fn input_names(&self) -> &[&str] {
+                &["A", "B", "C"]
+            }
+
+            fn input_y_offset(&self, idx: usize) -> usize {
+                match idx {
+                    0 => 0,
+                    1 => 2,
+                    2 => 3,
+                    _ => unreachable!(),
+                }
+            }
+
+            fn output_names(&self) -> &[&str] {
+                &["Y"]
+            }
+
+            fn output_y_offset(&self, idx: usize) -> usize {
+                match idx {
+                    0 => 2,
+                    _ => unreachable!(),
+                }
+            }
+        }
+    };
+}
+
+ComplexGate21!(Aoi21Gate, "AOI21", MeseconsGate::And, MeseconsGate::Nor);
+ComplexGate21!(Oai21Gate, "OAI21", MeseconsGate::Or, MeseconsGate::Nand);
+
+// (A op1 B) op2 (C op1 D), the second pair enters below the first one.
+macro_rules! ComplexGate22 {
+    ($gatename:ident, $yosys_id:literal, $first_gate:expr, $second_gate:expr) => {
+        #[derive(Copy, Clone, Debug)]
+        pub struct $gatename;
+        impl BasicCircuitDetails for $gatename {
+            fn yosys_id(&self) -> &str {
+                $yosys_id
+            }
+
+            fn get_layout(&self) -> &[BlockType] {
+                use BlockType::*;
+                &[
+                    WireCorner(CornerOrientation::LeftDown),
+                    Air,
+                    Gate($first_gate),
+                    WireCorner(CornerOrientation::LeftDown),
+                    WireCorner(CornerOrientation::LeftUp),
+                    Gate($second_gate),
+                    WireCorner(CornerOrientation::LeftDown),
+                    WireV,
+                    Gate($first_gate),
+                    WireCorner(CornerOrientation::LeftUp),
+                    WireCorner(CornerOrientation::LeftUp),
+                    Air,
+                ]
+            }
+
+            fn width(&self) -> usize {
+                2
+            }
+            fn height(&self) -> usize {
+                6
+            }
+            fn delay(&self) -> usize {
+                2
+            }
+            fn can_swap_input(&self) -> bool {
+                true
+            }
+
+            fn input_names(&self) -> &[&str] {
+                &["A", "B", "C", "D"]
+            }
+
+            fn input_y_offset(&self, idx: usize) -> usize {
+                match idx {
+                    0 => 0,
+                    1 => 2,
+                    2 => 3,
+                    3 => 5,
+                    _ => unreachable!(),
+                }
+            }
+
+            fn output_names(&self) -> &[&str] {
+                &["Y"]
+            }
+
+            fn output_y_offset(&self, idx: usize) -> usize {
+                match idx {
+                    0 => 2,
+                    _ => unreachable!(),
+                }
+            }
+        }
+    };
+}
+
+ComplexGate22!(Aoi22Gate, "AOI22", MeseconsGate::And, MeseconsGate::Nor);
+ComplexGate22!(Oai22Gate, "OAI22", MeseconsGate::Or, MeseconsGate::Nand);
@@ -84,6 +84,10 @@ impl BasicCircuitDetails for DffP {
     fn height(&self) -> usize {
         7
     }
+    // C -> Not -> And -> Nor -> Q
+    fn delay(&self) -> usize {
+        3
+    }
     fn can_swap_input(&self) -> bool {
         false
     }
@@ -111,3 +115,892 @@ impl BasicCircuitDetails for DffP {
         }
     }
 }
+
+// Master-slave flip-flop that latches D on the falling edge of C: the master
+// latch is open while C is high, the slave latch while C is low.
+#[derive(Copy, Clone, Debug)]
+pub struct DffN;
+
+impl BasicCircuitDetails for DffN {
+    fn yosys_id(&self) -> &str {
+        "DFFN"
+    }
+
+    fn get_layout(&self) -> &[BlockType] {
+        use BlockType::*;
+        use CornerOrientation::*;
+        &[
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            WireCorner(DownRight),
+            WireH,
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            WireCrossing,
+            WireT(TRotation::LeftRightDown),
+            WireCrossing,
+            Gate(MeseconsGate::Not),
+            WireCorner(LeftUp),
+            Air,
+            Gate(MeseconsGate::Nor),
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireH,
+            WireCrossing,
+            WireT(TRotation::LeftRightDown),
+            WireCrossing,
+            Gate(MeseconsGate::Not),
+            WireCorner(LeftUp),
+            Air,
+            Gate(MeseconsGate::Nor),
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireV,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireCorner(LeftDown),
+            Air,
+            WireV,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireCorner(LeftDown),
+            WireV,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireCorner(DownRight),
+            WireCorner(LeftUp),
+            WireV,
+            Air,
+            WireV,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireCorner(DownRight),
+            WireCorner(LeftUp),
+            WireV,
+            WireV,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Gate(MeseconsGate::Nor),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            WireV,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Gate(MeseconsGate::Nor),
+            WireH,
+            WireCorner(LeftUp),
+            WireV,
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            WireV,
+            Air,
+            WireCorner(UpRight),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            WireCorner(UpRight),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            Air,
+            WireCorner(UpRight),
+            Gate(MeseconsGate::Not),
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+        ]
+    }
+
+    fn width(&self) -> usize {
+        19
+    }
+    fn height(&self) -> usize {
+        9
+    }
+    // C -> Not -> And -> Nor -> Nor -> Q
+    fn delay(&self) -> usize {
+        4
+    }
+    fn can_swap_input(&self) -> bool {
+        false
+    }
+
+    fn input_names(&self) -> &[&str] {
+        &["C", "D"]
+    }
+
+    fn input_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 0,
+            1 => 2,
+            _ => unreachable!(),
+        }
+    }
+
+    fn output_names(&self) -> &[&str] {
+        &["Q"]
+    }
+
+    fn output_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 2,
+            _ => unreachable!(),
+        }
+    }
+}
+
+// Master-slave flip-flop with an asynchronous, active high reset. R forces
+// both latches to 0, regardless of C.
+#[derive(Copy, Clone, Debug)]
+pub struct DffPP0;
+
+impl BasicCircuitDetails for DffPP0 {
+    fn yosys_id(&self) -> &str {
+        "DFFR"
+    }
+
+    fn get_layout(&self) -> &[BlockType] {
+        use BlockType::*;
+        use CornerOrientation::*;
+        &[
+            WireT(TRotation::LeftRightDown),
+            Gate(MeseconsGate::Not),
+            WireH,
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            Air,
+            WireCorner(DownRight),
+            WireH,
+            WireH,
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            WireCrossing,
+            WireH,
+            WireT(TRotation::LeftRightDown),
+            WireCrossing,
+            Gate(MeseconsGate::Not),
+            WireCorner(LeftUp),
+            Air,
+            Gate(MeseconsGate::Or),
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Gate(MeseconsGate::Nor),
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireH,
+            WireCrossing,
+            WireH,
+            WireT(TRotation::LeftRightDown),
+            WireCrossing,
+            Gate(MeseconsGate::Not),
+            WireCorner(LeftUp),
+            Air,
+            Gate(MeseconsGate::Or),
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            WireV,
+            Air,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireV,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireCorner(LeftDown),
+            Air,
+            WireV,
+            Air,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Gate(MeseconsGate::Nor),
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireV,
+            Air,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireV,
+            WireCorner(DownRight),
+            WireCorner(LeftUp),
+            WireV,
+            Air,
+            WireV,
+            Air,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireV,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireCorner(LeftDown),
+            WireV,
+            Air,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            WireV,
+            Gate(MeseconsGate::Nor),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            WireV,
+            Air,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireV,
+            WireCorner(DownRight),
+            WireCorner(LeftUp),
+            WireV,
+            WireV,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCrossing,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            WireV,
+            Gate(MeseconsGate::Nor),
+            WireH,
+            WireCorner(LeftUp),
+            WireV,
+            Air,
+            Air,
+            WireCorner(UpRight),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            WireV,
+            Air,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCrossing,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            WireV,
+            WireCorner(DownRight),
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Air,
+            WireCorner(UpRight),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireCorner(LeftUp),
+            WireCorner(DownRight),
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            WireH,
+            WireT(TRotation::LeftRightUp),
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+        ]
+    }
+
+    fn width(&self) -> usize {
+        23
+    }
+    fn height(&self) -> usize {
+        12
+    }
+    // C -> And -> Nor -> Nor -> Q
+    fn delay(&self) -> usize {
+        3
+    }
+    fn can_swap_input(&self) -> bool {
+        false
+    }
+
+    fn input_names(&self) -> &[&str] {
+        &["C", "D", "R"]
+    }
+
+    fn input_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 0,
+            1 => 2,
+            2 => 11,
+            _ => unreachable!(),
+        }
+    }
+
+    fn output_names(&self) -> &[&str] {
+        &["Q"]
+    }
+
+    fn output_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 4,
+            _ => unreachable!(),
+        }
+    }
+}
+
+// Same as `DffPP0`, but with an active low reset.
+#[derive(Copy, Clone, Debug)]
+pub struct DffPN0;
+
+impl BasicCircuitDetails for DffPN0 {
+    fn yosys_id(&self) -> &str {
+        "DFFRN"
+    }
+
+    fn get_layout(&self) -> &[BlockType] {
+        use BlockType::*;
+        use CornerOrientation::*;
+        &[
+            WireT(TRotation::LeftRightDown),
+            Gate(MeseconsGate::Not),
+            WireH,
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            Air,
+            WireCorner(DownRight),
+            WireH,
+            WireH,
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            WireCrossing,
+            WireH,
+            WireT(TRotation::LeftRightDown),
+            WireCrossing,
+            Gate(MeseconsGate::Not),
+            WireCorner(LeftUp),
+            Air,
+            Gate(MeseconsGate::Or),
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Gate(MeseconsGate::Nor),
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireH,
+            WireCrossing,
+            WireH,
+            WireT(TRotation::LeftRightDown),
+            WireCrossing,
+            Gate(MeseconsGate::Not),
+            WireCorner(LeftUp),
+            Air,
+            Gate(MeseconsGate::Or),
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            WireV,
+            Air,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireV,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireCorner(LeftDown),
+            Air,
+            WireV,
+            Air,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Gate(MeseconsGate::Nor),
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireV,
+            Air,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireV,
+            WireCorner(DownRight),
+            WireCorner(LeftUp),
+            WireV,
+            Air,
+            WireV,
+            Air,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireV,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireCorner(LeftDown),
+            WireV,
+            Air,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            WireV,
+            Gate(MeseconsGate::Nor),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            WireV,
+            Air,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireV,
+            WireCorner(DownRight),
+            WireCorner(LeftUp),
+            WireV,
+            WireV,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCrossing,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            WireV,
+            Gate(MeseconsGate::Nor),
+            WireH,
+            WireCorner(LeftUp),
+            WireV,
+            Air,
+            Air,
+            WireCorner(UpRight),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            WireV,
+            Air,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCrossing,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            WireV,
+            WireCorner(DownRight),
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Air,
+            WireCorner(UpRight),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireCorner(LeftUp),
+            WireCorner(DownRight),
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            Gate(MeseconsGate::Not),
+            WireT(TRotation::LeftRightUp),
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+        ]
+    }
+
+    fn width(&self) -> usize {
+        23
+    }
+    fn height(&self) -> usize {
+        12
+    }
+    // C -> And -> Nor -> Nor -> Q
+    fn delay(&self) -> usize {
+        3
+    }
+    fn can_swap_input(&self) -> bool {
+        false
+    }
+
+    fn input_names(&self) -> &[&str] {
+        &["C", "D", "R"]
+    }
+
+    fn input_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 0,
+            1 => 2,
+            2 => 11,
+            _ => unreachable!(),
+        }
+    }
+
+    fn output_names(&self) -> &[&str] {
+        &["Q"]
+    }
+
+    fn output_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 4,
+            _ => unreachable!(),
+        }
+    }
+}
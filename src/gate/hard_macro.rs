@@ -0,0 +1,292 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Hard macros are pre-built blocks (ROMs, displays, ...) that are loaded from a
+// minetest schematic. They are placed as a black box and all of their nodes are
+// copied onto the canvas as they are, even the ones this project doesn't know.
+//
+// The pins are described in a JSON file next to the schematic, e.g. `rom.json`
+// for `rom.mts`:
+//
+//   {
+//     "yosys_id": "ROM16",
+//     "inputs": [{"name": "A0", "y": 0}, {"name": "A1", "y": 2}],
+//     "outputs": [{"name": "D0", "y": 1}],
+//     "delay": 6
+//   }
+//
+// Like for library cells, inputs enter the macro from the left on layer 0 and
+// outputs leave it to the right. The schematic is expected in the orientation
+// this project writes them in: its x axis are the rows of the macro, its z axis
+// the columns. A bottom layer that only consists of stone is the floor and is
+// skipped.
+//
+// Submodules that are kept as placement regions are hard macros as well, built
+// from the canvas they were placed and routed on.
+
+use crate::canvas::{Canvas, ForeignNode};
+use crate::gate::library::{leak_names, LibraryPinJson};
+use crate::gate::BasicCircuitDetails;
+use crate::BlockType;
+use byteorder::{BigEndian, ReadBytesExt};
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct HardMacroJson {
+    yosys_id: String,
+    inputs: Vec<LibraryPinJson>,
+    outputs: Vec<LibraryPinJson>,
+    delay: usize,
+}
+
+#[derive(Debug)]
+pub struct HardMacro {
+    yosys_id: String,
+    // One layout per layer, starting at layer 0.
+    layers: Vec<Vec<BlockType>>,
+    width: usize,
+    height: usize,
+    delay: usize,
+    input_names: Vec<&'static str>,
+    input_y_offsets: Vec<usize>,
+    output_names: Vec<&'static str>,
+    output_y_offsets: Vec<usize>,
+    // Whether the macro holds state, so that its outputs can be fed back.
+    sequential: bool,
+}
+
+// The nodes of a schematic, layer by layer.
+struct Schematic {
+    rows: usize,
+    columns: usize,
+    layers: Vec<Vec<BlockType>>,
+}
+
+fn read_string(file: &mut File) -> std::io::Result<String> {
+    let len = file.read_u16::<BigEndian>()?;
+    let mut buf = vec![0u8; len as usize];
+    file.read_exact(&mut buf)?;
+    Ok(String::from_utf8(buf).unwrap_or_else(|_| panic!("Invalid node name in schematic")))
+}
+
+fn read_mts(filepath: &str) -> std::io::Result<Schematic> {
+    let mut file = File::open(filepath)?;
+    let mut signature = [0u8; 4];
+    file.read_exact(&mut signature)?;
+    if &signature != b"MTSM" {
+        panic!("{} is not a minetest schematic", filepath);
+    }
+    let version = file.read_u16::<BigEndian>()?;
+    if version == 0 || version > 4 {
+        panic!("{}: Unsupported schematic version {}", filepath, version);
+    }
+
+    let size_x = file.read_i16::<BigEndian>()? as usize;
+    let size_y = file.read_i16::<BigEndian>()? as usize;
+    let size_z = file.read_i16::<BigEndian>()? as usize;
+    if version >= 3 {
+        // Probability of each layer being placed, not needed here.
+        let mut probabilities = vec![0u8; size_y];
+        file.read_exact(&mut probabilities)?;
+    }
+
+    let n_names = file.read_u16::<BigEndian>()?;
+    let names = (0..n_names)
+        .map(|_| read_string(&mut file))
+        .collect::<std::io::Result<Vec<_>>>()?;
+    // Circuits refer to their nodes for the whole run.
+    let names = names
+        .into_iter()
+        .map(|n| &*Box::leak(n.into_boxed_str()))
+        .collect::<Vec<_>>();
+
+    let mut compressed = Vec::new();
+    file.read_to_end(&mut compressed)?;
+    let data = inflate::inflate_bytes_zlib(&compressed)
+        .unwrap_or_else(|e| panic!("{}: Could not decompress the nodes: {}", filepath, e));
+    let n = size_x * size_y * size_z;
+    if data.len() < 4 * n {
+        panic!("{}: Schematic is truncated", filepath);
+    }
+
+    // Nodes are stored as node ids, then param1 and then param2 for every
+    // node, iterating over z, y and x.
+    let index = |x: usize, y: usize, z: usize| (z * size_y + y) * size_x + x;
+    let name = |idx: usize| {
+        let id = (data[2 * idx] as usize) << 8 | data[2 * idx + 1] as usize;
+        *names
+            .get(id)
+            .unwrap_or_else(|| panic!("{}: Unknown node id {}", filepath, id))
+    };
+
+    let has_floor =
+        size_y > 1 && (0..size_x).all(|x| (0..size_z).all(|z| name(index(x, 0, z)) == "stone"));
+    let layers = (if has_floor { 1 } else { 0 }..size_y)
+        .map(|y| {
+            let mut layout = Vec::with_capacity(size_x * size_z);
+            for x in 0..size_x {
+                for z in 0..size_z {
+                    let idx = index(x, y, z);
+                    layout.push(match name(idx) {
+                        "air" | "ignore" => BlockType::Air,
+                        name => BlockType::Foreign(ForeignNode {
+                            name,
+                            param2: data[3 * n + idx],
+                        }),
+                    });
+                }
+            }
+            layout
+        })
+        .collect();
+    Ok(Schematic {
+        rows: size_x,
+        columns: size_z,
+        layers,
+    })
+}
+
+impl HardMacro {
+    pub fn load(filepath: &str) -> std::io::Result<Self> {
+        let pin_file = Path::new(filepath).with_extension("json");
+        let mut file = File::open(&pin_file)?;
+        let mut buf = String::new();
+        file.read_to_string(&mut buf)?;
+        let pins: HardMacroJson = serde_json::from_str(&buf)?;
+
+        let Schematic {
+            rows: height,
+            columns: width,
+            layers,
+        } = read_mts(filepath)?;
+        if width == 0 || height == 0 || layers.is_empty() {
+            panic!("Hard macro {}: The schematic is empty", pins.yosys_id);
+        }
+        if pins.outputs.is_empty() {
+            panic!(
+                "Hard macro {}: At least one output is required",
+                pins.yosys_id
+            );
+        }
+        for pin in pins.inputs.iter().chain(pins.outputs.iter()) {
+            if pin.y >= height {
+                panic!(
+                    "Hard macro {}: Pin {} is outside of the schematic",
+                    pins.yosys_id, pin.name
+                );
+            }
+        }
+
+        Ok(Self {
+            input_names: leak_names(&pins.inputs),
+            input_y_offsets: pins.inputs.iter().map(|p| p.y).collect(),
+            output_names: leak_names(&pins.outputs),
+            output_y_offsets: pins.outputs.iter().map(|p| p.y).collect(),
+            yosys_id: pins.yosys_id,
+            layers,
+            width,
+            height,
+            delay: pins.delay,
+            sequential: false,
+        })
+    }
+
+    // Uses all blocks of the canvas. The pins are given as (name, row).
+    pub fn from_canvas(
+        yosys_id: &str,
+        canvas: &Canvas,
+        inputs: &[(String, usize)],
+        outputs: &[(String, usize)],
+        delay: usize,
+        sequential: bool,
+    ) -> Self {
+        let (width, height) = canvas.dimensions();
+        let layers = (0..canvas.layers())
+            .map(|layer| {
+                (0..height)
+                    .flat_map(|y| (0..width).map(move |x| (x, y)))
+                    .map(|(x, y)| canvas.get_layer(x, y, layer))
+                    .collect()
+            })
+            .collect();
+        let leak = |pins: &[(String, usize)]| {
+            pins.iter()
+                .map(|(name, _)| &*Box::leak(name.clone().into_boxed_str()))
+                .collect()
+        };
+
+        Self {
+            yosys_id: yosys_id.to_string(),
+            layers,
+            width,
+            height,
+            delay,
+            input_names: leak(inputs),
+            input_y_offsets: inputs.iter().map(|&(_, y)| y).collect(),
+            output_names: leak(outputs),
+            output_y_offsets: outputs.iter().map(|&(_, y)| y).collect(),
+            sequential,
+        }
+    }
+
+    pub fn layers(&self) -> &[Vec<BlockType>] {
+        &self.layers
+    }
+
+    pub fn is_sequential(&self) -> bool {
+        self.sequential
+    }
+}
+
+impl BasicCircuitDetails for HardMacro {
+    fn yosys_id(&self) -> &str {
+        &self.yosys_id
+    }
+
+    fn get_layout(&self) -> &[BlockType] {
+        &self.layers[0]
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+    fn height(&self) -> usize {
+        self.height
+    }
+    fn delay(&self) -> usize {
+        self.delay
+    }
+    fn can_swap_input(&self) -> bool {
+        false
+    }
+
+    fn input_names(&self) -> &[&str] {
+        &self.input_names
+    }
+
+    fn input_y_offset(&self, idx: usize) -> usize {
+        self.input_y_offsets[idx]
+    }
+
+    fn output_names(&self) -> &[&str] {
+        &self.output_names
+    }
+
+    fn output_y_offset(&self, idx: usize) -> usize {
+        self.output_y_offsets[idx]
+    }
+}
@@ -0,0 +1,212 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::gate::{BasicCircuitDetails, MeseconsGate};
+use crate::{BlockType, CornerOrientation, TRotation};
+
+// Gated D latch, Q follows D while E is high.
+#[derive(Copy, Clone, Debug)]
+pub struct DLatchP;
+
+impl BasicCircuitDetails for DLatchP {
+    fn yosys_id(&self) -> &str {
+        "DLATCH"
+    }
+
+    fn get_layout(&self) -> &[BlockType] {
+        use BlockType::*;
+        use CornerOrientation::*;
+        &[
+            WireH,
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            Air,
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            WireT(TRotation::LeftRightDown),
+            WireCrossing,
+            Gate(MeseconsGate::Not),
+            WireCorner(LeftUp),
+            Air,
+            Gate(MeseconsGate::Nor),
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireCorner(LeftDown),
+            WireV,
+            WireV,
+            Air,
+            Air,
+            Air,
+            WireCorner(DownRight),
+            WireCorner(LeftUp),
+            WireV,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Gate(MeseconsGate::Nor),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            WireV,
+            Air,
+            Gate(MeseconsGate::And),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            WireCorner(UpRight),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+            Air,
+            Air,
+        ]
+    }
+
+    fn width(&self) -> usize {
+        8
+    }
+    fn height(&self) -> usize {
+        8
+    }
+    // E -> And -> Nor -> Nor -> Q
+    fn delay(&self) -> usize {
+        3
+    }
+    fn can_swap_input(&self) -> bool {
+        false
+    }
+
+    fn input_names(&self) -> &[&str] {
+        &["E", "D"]
+    }
+
+    fn input_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 0,
+            1 => 2,
+            _ => unreachable!(),
+        }
+    }
+
+    fn output_names(&self) -> &[&str] {
+        &["Q"]
+    }
+
+    fn output_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 2,
+            _ => unreachable!(),
+        }
+    }
+}
+
+// Set/reset latch built from two cross-coupled Nor gates, S and R are active
+// high. Setting both at the same time is undefined.
+#[derive(Copy, Clone, Debug)]
+pub struct SrLatch;
+
+impl BasicCircuitDetails for SrLatch {
+    fn yosys_id(&self) -> &str {
+        "SR"
+    }
+
+    fn get_layout(&self) -> &[BlockType] {
+        use BlockType::*;
+        use CornerOrientation::*;
+        &[
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            Gate(MeseconsGate::Nor),
+            WireT(TRotation::LeftRightDown),
+            WireH,
+            Air,
+            WireCorner(UpRight),
+            WireCrossing,
+            WireCorner(LeftDown),
+            Air,
+            WireCorner(DownRight),
+            WireCorner(LeftUp),
+            WireV,
+            Air,
+            Gate(MeseconsGate::Nor),
+            WireH,
+            WireCorner(LeftUp),
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+            Air,
+        ]
+    }
+
+    fn width(&self) -> usize {
+        4
+    }
+    fn height(&self) -> usize {
+        6
+    }
+    // S -> Nor -> Nor -> Q
+    fn delay(&self) -> usize {
+        2
+    }
+    fn can_swap_input(&self) -> bool {
+        false
+    }
+
+    fn input_names(&self) -> &[&str] {
+        &["S", "R"]
+    }
+
+    fn input_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 5,
+            1 => 0,
+            _ => unreachable!(),
+        }
+    }
+
+    fn output_names(&self) -> &[&str] {
+        &["Q"]
+    }
+
+    fn output_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 1,
+            _ => unreachable!(),
+        }
+    }
+}
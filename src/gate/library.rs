@@ -0,0 +1,216 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Cells that are loaded from a JSON cell library at runtime instead of being
+// compiled in. A library is a list of cells like this one:
+//
+//   {
+//     "yosys_id": "AND3",
+//     "layout": [
+//       [{"WireCorner": "LeftDown"}, "Air"],
+//       [{"Gate": "And"}, {"WireCorner": "LeftDown"}],
+//       [{"WireCorner": "LeftUp"}, {"Gate": "And"}],
+//       ["WireH", {"WireCorner": "LeftUp"}]
+//     ],
+//     "inputs": [{"name": "A", "y": 0}, {"name": "B", "y": 2}, {"name": "C", "y": 3}],
+//     "outputs": [{"name": "Y", "y": 2}],
+//     "can_swap_input": true,
+//     "delay": 2
+//   }
+//
+// The layout is given row by row, using the names of `BlockType`. Inputs enter
+// the cell from the left, outputs leave it to the right.
+
+use crate::gate::hard_macro::HardMacro;
+use crate::gate::{BasicCircuitDetails, BasicCircuitYada};
+use crate::BlockType;
+use serde::Deserialize;
+use std::fs::File;
+use std::io::Read;
+
+#[derive(Deserialize)]
+pub(super) struct LibraryPinJson {
+    pub name: String,
+    pub y: usize,
+}
+
+#[derive(Deserialize)]
+struct LibraryCellJson {
+    yosys_id: String,
+    layout: Vec<Vec<BlockType>>,
+    inputs: Vec<LibraryPinJson>,
+    outputs: Vec<LibraryPinJson>,
+    #[serde(default)]
+    can_swap_input: bool,
+    delay: usize,
+}
+
+#[derive(Debug)]
+pub struct LibraryCell {
+    yosys_id: String,
+    layout: Vec<BlockType>,
+    width: usize,
+    height: usize,
+    delay: usize,
+    can_swap_input: bool,
+    input_names: Vec<&'static str>,
+    input_y_offsets: Vec<usize>,
+    output_names: Vec<&'static str>,
+    output_y_offsets: Vec<usize>,
+}
+
+// Circuits refer to their cells for the whole run, so cells (and their pin
+// names) are never freed.
+pub(super) fn leak_names(pins: &[LibraryPinJson]) -> Vec<&'static str> {
+    pins.iter()
+        .map(|p| &*Box::leak(p.name.clone().into_boxed_str()))
+        .collect()
+}
+
+impl LibraryCell {
+    fn from_json(cell: LibraryCellJson) -> Self {
+        let height = cell.layout.len();
+        let width = cell.layout.first().map(|row| row.len()).unwrap_or(0);
+        if width == 0 || cell.layout.iter().any(|row| row.len() != width) {
+            panic!(
+                "Cell {}: All layout rows need the same, non-zero length",
+                cell.yosys_id
+            );
+        }
+        if cell.outputs.is_empty() {
+            panic!("Cell {}: At least one output is required", cell.yosys_id);
+        }
+        if cell.can_swap_input && cell.inputs.len() != 2 {
+            panic!(
+                "Cell {}: Only cells with two inputs can swap them",
+                cell.yosys_id
+            );
+        }
+        for pin in cell.inputs.iter().chain(cell.outputs.iter()) {
+            if pin.y >= height {
+                panic!(
+                    "Cell {}: Pin {} is outside of the layout",
+                    cell.yosys_id, pin.name
+                );
+            }
+        }
+
+        Self {
+            input_names: leak_names(&cell.inputs),
+            input_y_offsets: cell.inputs.iter().map(|p| p.y).collect(),
+            output_names: leak_names(&cell.outputs),
+            output_y_offsets: cell.outputs.iter().map(|p| p.y).collect(),
+            layout: cell.layout.into_iter().flatten().collect(),
+            yosys_id: cell.yosys_id,
+            width,
+            height,
+            delay: cell.delay,
+            can_swap_input: cell.can_swap_input,
+        }
+    }
+}
+
+impl BasicCircuitDetails for LibraryCell {
+    fn yosys_id(&self) -> &str {
+        &self.yosys_id
+    }
+
+    fn get_layout(&self) -> &[BlockType] {
+        &self.layout
+    }
+
+    fn width(&self) -> usize {
+        self.width
+    }
+    fn height(&self) -> usize {
+        self.height
+    }
+    fn delay(&self) -> usize {
+        self.delay
+    }
+    fn can_swap_input(&self) -> bool {
+        self.can_swap_input
+    }
+
+    fn input_names(&self) -> &[&str] {
+        &self.input_names
+    }
+
+    fn input_y_offset(&self, idx: usize) -> usize {
+        self.input_y_offsets[idx]
+    }
+
+    fn output_names(&self) -> &[&str] {
+        &self.output_names
+    }
+
+    fn output_y_offset(&self, idx: usize) -> usize {
+        self.output_y_offsets[idx]
+    }
+}
+
+#[derive(Default)]
+pub struct CellLibrary {
+    cells: Vec<&'static LibraryCell>,
+    hard_macros: Vec<&'static HardMacro>,
+}
+
+impl CellLibrary {
+    pub fn load(filepath: &str) -> std::io::Result<Self> {
+        let mut file = File::open(filepath)?;
+        let mut buf = String::new();
+        file.read_to_string(&mut buf)?;
+
+        let cells: Vec<LibraryCellJson> = serde_json::from_str(&buf)?;
+        Ok(Self {
+            cells: cells
+                .into_iter()
+                .map(|c| &*Box::leak(Box::new(LibraryCell::from_json(c))))
+                .collect(),
+            hard_macros: Vec::new(),
+        })
+    }
+
+    pub fn load_hard_macro(&mut self, filepath: &str) -> std::io::Result<BasicCircuitYada> {
+        Ok(self.add_hard_macro(HardMacro::load(filepath)?))
+    }
+
+    pub fn add_hard_macro(&mut self, hard_macro: HardMacro) -> BasicCircuitYada {
+        let hard_macro = &*Box::leak(Box::new(hard_macro));
+        self.hard_macros.push(hard_macro);
+        BasicCircuitYada::HardMacro(hard_macro)
+    }
+
+    pub fn n_cells(&self) -> usize {
+        self.cells.len()
+    }
+
+    pub fn cells(&self) -> impl Iterator<Item = BasicCircuitYada> + '_ {
+        self.cells.iter().map(|&c| BasicCircuitYada::Library(c))
+    }
+
+    // Cells of the library take precedence over the built-in ones.
+    pub fn get(&self, cell_type: &str) -> Option<BasicCircuitYada> {
+        self.cells
+            .iter()
+            .find(|c| c.yosys_id == cell_type)
+            .map(|&c| BasicCircuitYada::Library(c))
+            .or_else(|| {
+                self.hard_macros
+                    .iter()
+                    .find(|m| m.yosys_id() == cell_type)
+                    .map(|&m| BasicCircuitYada::HardMacro(m))
+            })
+    }
+}
@@ -14,14 +14,27 @@
 use crate::BlockType;
 use std::convert::TryFrom;
 
+mod adder;
+mod alias;
 mod basic;
 mod dff;
+mod hard_macro;
+mod latch;
+mod library;
 mod mesecons;
+mod mux;
 
+pub use alias::CellAlias;
 pub use basic::*;
+pub use hard_macro::HardMacro;
+pub use library::CellLibrary;
 pub use mesecons::MeseconsGate;
+pub use mux::MuxGate;
 
-use dff::DffP;
+use adder::{FullAdder, HalfAdder};
+use dff::{DffN, DffP, DffPN0, DffPP0};
+use latch::{DLatchP, SrLatch};
+use library::LibraryCell;
 
 #[derive(Debug, Copy, Clone)]
 pub enum BasicCircuitYada {
@@ -34,13 +47,29 @@ pub enum BasicCircuitYada {
     Not(NotGate),
     Xnor(XnorGate),
     Xor(XorGate),
+    Aoi21(Aoi21Gate),
+    Oai21(Oai21Gate),
+    Aoi22(Aoi22Gate),
+    Oai22(Oai22Gate),
+    Mux(MuxGate),
+    HalfAdder(HalfAdder),
+    FullAdder(FullAdder),
 
     InputGate(InputGate),
     OutputGate(OutputGate),
     ForwardGate(ForwardGate),
+    FeedbackGate(FeedbackGate),
     BufGate(BufGate),
 
     DffP(DffP),
+    DffN(DffN),
+    DffPP0(DffPP0),
+    DffPN0(DffPN0),
+    DLatchP(DLatchP),
+    SrLatch(SrLatch),
+
+    Library(&'static LibraryCell),
+    HardMacro(&'static HardMacro),
 }
 
 impl BasicCircuitYada {
@@ -56,10 +85,96 @@ impl BasicCircuitYada {
         Self::OutputGate(OutputGate)
     }
 
+    pub fn is_output(&self) -> bool {
+        matches!(self, BasicCircuitYada::OutputGate(_))
+    }
+
     pub fn forward() -> Self {
         Self::ForwardGate(ForwardGate)
     }
 
+    pub fn feedback() -> Self {
+        Self::FeedbackGate(FeedbackGate)
+    }
+
+    pub fn is_feedback(&self) -> bool {
+        matches!(self, BasicCircuitYada::FeedbackGate(_))
+    }
+
+    // Whether the gate holds its state (flip-flops and latches), so that its
+    // outputs can be fed back to the beginning of the circuit.
+    pub fn is_sequential(&self) -> bool {
+        matches!(
+            self,
+            BasicCircuitYada::DffP(_)
+                | BasicCircuitYada::DffN(_)
+                | BasicCircuitYada::DffPP0(_)
+                | BasicCircuitYada::DffPN0(_)
+                | BasicCircuitYada::DLatchP(_)
+                | BasicCircuitYada::SrLatch(_)
+        ) || matches!(self, BasicCircuitYada::HardMacro(m) if m.is_sequential())
+    }
+
+    // Index of the clock (or enable) input of sequential gates. Set/reset
+    // latches don't have one.
+    pub fn clock_input(&self) -> Option<usize> {
+        match self {
+            BasicCircuitYada::DffP(_)
+            | BasicCircuitYada::DffN(_)
+            | BasicCircuitYada::DffPP0(_)
+            | BasicCircuitYada::DffPN0(_)
+            | BasicCircuitYada::DLatchP(_) => Some(0),
+            _ => None,
+        }
+    }
+
+    // All cells that can be instantiated by yosys.
+    pub fn cells() -> &'static [Self] {
+        &[
+            Self::And(AndGate),
+            Self::Nand(NandGate),
+            Self::AndNot(AndNotGate),
+            Self::Or(OrGate),
+            Self::Nor(NorGate),
+            Self::OrNot(OrNotGate),
+            Self::Not(NotGate),
+            Self::Xnor(XnorGate),
+            Self::Xor(XorGate),
+            Self::Aoi21(Aoi21Gate),
+            Self::Oai21(Oai21Gate),
+            Self::Aoi22(Aoi22Gate),
+            Self::Oai22(Oai22Gate),
+            Self::Mux(MuxGate),
+            Self::HalfAdder(HalfAdder),
+            Self::FullAdder(FullAdder),
+            Self::DffP(DffP),
+            Self::DffN(DffN),
+            Self::DffPP0(DffPP0),
+            Self::DffPN0(DffPN0),
+            Self::DLatchP(DLatchP),
+            Self::SrLatch(SrLatch),
+
+            Self::BufGate(BufGate),
+        ]
+    }
+
+    // State of sequential gates in Liberty syntax, their outputs provide `IQ`.
+    pub fn liberty_state(&self) -> Option<&'static str> {
+        match self {
+            BasicCircuitYada::DffP(_) => Some("ff(IQ, IQN) { clocked_on: C; next_state: D; }"),
+            BasicCircuitYada::DffN(_) => Some("ff(IQ, IQN) { clocked_on: \"C'\"; next_state: D; }"),
+            BasicCircuitYada::DffPP0(_) => {
+                Some("ff(IQ, IQN) { clocked_on: C; next_state: D; clear: R; }")
+            }
+            BasicCircuitYada::DffPN0(_) => {
+                Some("ff(IQ, IQN) { clocked_on: C; next_state: D; clear: \"R'\"; }")
+            }
+            BasicCircuitYada::DLatchP(_) => Some("latch(IQ, IQN) { enable: E; data_in: D; }"),
+            BasicCircuitYada::SrLatch(_) => Some("latch(IQ, IQN) { preset: S; clear: R; }"),
+            _ => None,
+        }
+    }
+
     fn inner(&self) -> &dyn BasicCircuitDetails {
         match self {
             BasicCircuitYada::And(ref x) => x,
@@ -71,13 +186,29 @@ impl BasicCircuitYada {
             BasicCircuitYada::Not(ref x) => x,
             BasicCircuitYada::Xnor(ref x) => x,
             BasicCircuitYada::Xor(ref x) => x,
+            BasicCircuitYada::Aoi21(ref x) => x,
+            BasicCircuitYada::Oai21(ref x) => x,
+            BasicCircuitYada::Aoi22(ref x) => x,
+            BasicCircuitYada::Oai22(ref x) => x,
+            BasicCircuitYada::Mux(ref x) => x,
+            BasicCircuitYada::HalfAdder(ref x) => x,
+            BasicCircuitYada::FullAdder(ref x) => x,
 
             BasicCircuitYada::InputGate(ref x) => x,
             BasicCircuitYada::OutputGate(ref x) => x,
             BasicCircuitYada::ForwardGate(ref x) => x,
+            BasicCircuitYada::FeedbackGate(ref x) => x,
             BasicCircuitYada::BufGate(ref x) => x,
 
             BasicCircuitYada::DffP(ref x) => x,
+            BasicCircuitYada::DffN(ref x) => x,
+            BasicCircuitYada::DffPP0(ref x) => x,
+            BasicCircuitYada::DffPN0(ref x) => x,
+            BasicCircuitYada::DLatchP(ref x) => x,
+            BasicCircuitYada::SrLatch(ref x) => x,
+
+            BasicCircuitYada::Library(x) => *x,
+            BasicCircuitYada::HardMacro(x) => *x,
         }
     }
 }
@@ -86,22 +217,7 @@ impl TryFrom<&str> for BasicCircuitYada {
     type Error = ();
 
     fn try_from(cell_type: &str) -> Result<Self, Self::Error> {
-        let gates: &[Self] = &[
-            Self::And(AndGate),
-            Self::Nand(NandGate),
-            Self::AndNot(AndNotGate),
-            Self::Or(OrGate),
-            Self::Nor(NorGate),
-            Self::OrNot(OrNotGate),
-            Self::Not(NotGate),
-            Self::Xnor(XnorGate),
-            Self::Xor(XorGate),
-            Self::DffP(DffP),
-
-            Self::BufGate(BufGate),
-        ];
-
-        for gate in gates {
+        for gate in Self::cells() {
             if gate.yosys_id() == cell_type {
                 return Ok(*gate);
             }
@@ -123,6 +239,9 @@ impl BasicCircuitDetails for BasicCircuitYada {
     fn height(&self) -> usize {
         self.inner().height()
     }
+    fn delay(&self) -> usize {
+        self.inner().delay()
+    }
     fn can_swap_input(&self) -> bool {
         self.inner().can_swap_input()
     }
@@ -145,6 +264,8 @@ pub trait BasicCircuitDetails {
     fn get_layout(&self) -> &[BlockType];
     fn width(&self) -> usize;
     fn height(&self) -> usize;
+    // Number of mesecons gates on the longest path from an input to an output.
+    fn delay(&self) -> usize;
 
     fn input_names(&self) -> &[&str];
     fn input_y_offset(&self, idx: usize) -> usize;
@@ -0,0 +1,94 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+use crate::gate::{BasicCircuitDetails, MeseconsGate};
+use crate::{BlockType, CornerOrientation, TRotation};
+
+// Y = S ? B : A, built from (A & !S) | (B & S).
+#[derive(Copy, Clone, Debug)]
+pub struct MuxGate;
+
+impl BasicCircuitDetails for MuxGate {
+    fn yosys_id(&self) -> &str {
+        "MUX"
+    }
+
+    fn get_layout(&self) -> &[BlockType] {
+        use BlockType::*;
+        use CornerOrientation::*;
+        &[
+            WireH,
+            WireH,
+            WireCorner(LeftDown),
+            Air,
+            Air,
+            Air,
+            Gate(MeseconsGate::And),
+            WireCorner(LeftDown),
+            WireT(TRotation::LeftRightDown),
+            Gate(MeseconsGate::Not),
+            WireCorner(LeftUp),
+            Gate(MeseconsGate::Or),
+            WireCorner(UpRight),
+            WireH,
+            WireCorner(LeftDown),
+            WireV,
+            Air,
+            Air,
+            Gate(MeseconsGate::And),
+            WireCorner(LeftUp),
+            WireH,
+            WireH,
+            WireCorner(LeftUp),
+            Air,
+        ]
+    }
+
+    fn width(&self) -> usize {
+        4
+    }
+    fn height(&self) -> usize {
+        6
+    }
+    // S -> Not -> And -> Or -> Y
+    fn delay(&self) -> usize {
+        3
+    }
+    fn can_swap_input(&self) -> bool {
+        false
+    }
+
+    fn input_names(&self) -> &[&str] {
+        &["A", "B", "S"]
+    }
+
+    fn input_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 0,
+            1 => 5,
+            2 => 2,
+            _ => unreachable!(),
+        }
+    }
+
+    fn output_names(&self) -> &[&str] {
+        &["Y"]
+    }
+
+    fn output_y_offset(&self, idx: usize) -> usize {
+        match idx {
+            0 => 2,
+            _ => unreachable!(),
+        }
+    }
+}
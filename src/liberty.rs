@@ -0,0 +1,475 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Generates a Liberty file for all cells that can be placed, so that yosys only
+// maps to those.
+//
+// The boolean function of combinational cells is derived by simulating their
+// layout for every combination of inputs, so it can't drift from what is
+// actually placed. The area is the footprint of the cell in blocks.
+
+use crate::canvas::{BlockType, CornerOrientation, TRotation};
+use crate::gate::{BasicCircuitDetails, BasicCircuitYada, CellLibrary, MeseconsGate};
+use std::cmp;
+use std::fs::File;
+use std::io::Write;
+
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Side {
+    Left,
+    Right,
+    Up,
+    Down,
+}
+
+impl Side {
+    fn opposite(self) -> Self {
+        match self {
+            Side::Left => Side::Right,
+            Side::Right => Side::Left,
+            Side::Up => Side::Down,
+            Side::Down => Side::Up,
+        }
+    }
+
+    fn step(self, x: isize, y: isize) -> (isize, isize) {
+        match self {
+            Side::Left => (x - 1, y),
+            Side::Right => (x + 1, y),
+            Side::Up => (x, y - 1),
+            Side::Down => (x, y + 1),
+        }
+    }
+}
+
+// Sides of a block that are connected to each other, e.g. two groups for a
+// crossing.
+fn wire_groups(block: BlockType) -> Vec<Vec<Side>> {
+    use BlockType::*;
+    use Side::*;
+    match block {
+        WireH | Gate(MeseconsGate::Forward) => vec![vec![Left, Right]],
+        WireV => vec![vec![Up, Down]],
+        WireCrossing => vec![vec![Left, Right], vec![Up, Down]],
+        WireStar | Constant => vec![vec![Left, Right, Up, Down]],
+        WireCorner(CornerOrientation::LeftUp) => vec![vec![Left, Up]],
+        WireCorner(CornerOrientation::LeftDown) => vec![vec![Left, Down]],
+        WireCorner(CornerOrientation::DownRight) => vec![vec![Down, Right]],
+        WireCorner(CornerOrientation::UpRight) => vec![vec![Up, Right]],
+        WireT(TRotation::LeftRightDown) => vec![vec![Left, Right, Down]],
+        WireT(TRotation::LeftRightUp) => vec![vec![Left, Right, Up]],
+        WireT(TRotation::RightUpDown) => vec![vec![Right, Up, Down]],
+        WireT(TRotation::LeftUpDown) => vec![vec![Left, Up, Down]],
+        Air | Via | Gate(_) | Foreign(_) => vec![],
+    }
+}
+
+fn gate_inputs(gate: MeseconsGate) -> &'static [Side] {
+    match gate {
+        MeseconsGate::Not => &[Side::Left],
+        MeseconsGate::Input | MeseconsGate::Output | MeseconsGate::Forward => &[],
+        _ => &[Side::Up, Side::Down],
+    }
+}
+
+fn gate_output(gate: MeseconsGate, inputs: &[bool]) -> bool {
+    match gate {
+        MeseconsGate::Not => !inputs[0],
+        MeseconsGate::And => inputs[0] && inputs[1],
+        MeseconsGate::Nand => !(inputs[0] && inputs[1]),
+        MeseconsGate::Or => inputs[0] || inputs[1],
+        MeseconsGate::Nor => !(inputs[0] || inputs[1]),
+        MeseconsGate::Xor => inputs[0] != inputs[1],
+        MeseconsGate::Input | MeseconsGate::Output | MeseconsGate::Forward => unreachable!(),
+    }
+}
+
+struct SimGate {
+    gate: MeseconsGate,
+    inputs: Vec<Option<usize>>,
+    output: Option<usize>,
+}
+
+// The layout of a cell as a set of nets connected by gates.
+struct Simulation {
+    n_nets: usize,
+    input_pins: Vec<usize>,
+    output_pins: Vec<usize>,
+    constants: Vec<usize>,
+    gates: Vec<SimGate>,
+}
+
+pub fn find(parent: &mut [usize], mut node: usize) -> usize {
+    while parent[node] != node {
+        parent[node] = parent[parent[node]];
+        node = parent[node];
+    }
+    node
+}
+
+impl Simulation {
+    fn new(cell: &dyn BasicCircuitDetails) -> Self {
+        let (w, h) = (cell.width(), cell.height());
+        let layout = cell.get_layout();
+        let block = |x: isize, y: isize| {
+            if x < 0 || y < 0 || x >= w as isize || y >= h as isize {
+                BlockType::Air
+            } else {
+                layout[y as usize * w + x as usize]
+            }
+        };
+
+        // One node per pin, per gate output and per group of connected sides
+        // of a wire.
+        let mut nodes = Vec::new();
+        for idx in 0..cell.input_names().len() {
+            nodes.push(((-1, cell.input_y_offset(idx) as isize), vec![Side::Right]));
+        }
+        for idx in 0..cell.output_names().len() {
+            nodes.push((
+                (w as isize, cell.output_y_offset(idx) as isize),
+                vec![Side::Left],
+            ));
+        }
+        for y in 0..h as isize {
+            for x in 0..w as isize {
+                match block(x, y) {
+                    BlockType::Gate(gate) if !gate_inputs(gate).is_empty() => {
+                        nodes.push(((x, y), vec![Side::Right]));
+                    }
+                    b => {
+                        for group in wire_groups(b) {
+                            nodes.push(((x, y), group));
+                        }
+                    }
+                }
+            }
+        }
+        let node_at = |(x, y): (isize, isize), side: Side| {
+            nodes
+                .iter()
+                .position(|(p, group)| *p == (x, y) && group.contains(&side))
+        };
+
+        let mut parent = (0..nodes.len()).collect::<Vec<_>>();
+        for (idx, &((x, y), ref group)) in nodes.iter().enumerate() {
+            for side in group.iter() {
+                if let Some(other) = node_at(side.step(x, y), side.opposite()) {
+                    let (a, b) = (find(&mut parent, idx), find(&mut parent, other));
+                    parent[a] = b;
+                }
+            }
+        }
+        let mut net = |node: usize| find(&mut parent, node);
+
+        let n_inputs = cell.input_names().len();
+        let mut gates = Vec::new();
+        let mut constants = Vec::new();
+        for y in 0..h as isize {
+            for x in 0..w as isize {
+                match block(x, y) {
+                    BlockType::Gate(gate) if !gate_inputs(gate).is_empty() => {
+                        gates.push(SimGate {
+                            gate,
+                            inputs: gate_inputs(gate)
+                                .iter()
+                                .map(|&side| {
+                                    node_at(side.step(x, y), side.opposite()).map(&mut net)
+                                })
+                                .collect(),
+                            output: node_at((x, y), Side::Right).map(&mut net),
+                        });
+                    }
+                    BlockType::Constant => {
+                        constants.push(net(node_at((x, y), Side::Left).unwrap()))
+                    }
+                    _ => {}
+                }
+            }
+        }
+        Simulation {
+            n_nets: nodes.len(),
+            input_pins: (0..n_inputs).map(&mut net).collect(),
+            output_pins: (n_inputs..n_inputs + cell.output_names().len())
+                .map(&mut net)
+                .collect(),
+            constants,
+            gates,
+        }
+    }
+
+    // Applies the inputs until all gates have settled.
+    fn run(&self, cell: &dyn BasicCircuitDetails, inputs: &[bool]) -> Vec<bool> {
+        let mut outputs = vec![false; self.gates.len()];
+        for _ in 0..=self.gates.len() + 1 {
+            let mut powered = vec![false; self.n_nets];
+            for (&net, &value) in self.input_pins.iter().zip(inputs.iter()) {
+                powered[net] |= value;
+            }
+            for &net in self.constants.iter() {
+                powered[net] = true;
+            }
+            for (gate, &value) in self.gates.iter().zip(outputs.iter()) {
+                if let Some(net) = gate.output {
+                    powered[net] |= value;
+                }
+            }
+
+            let next = self
+                .gates
+                .iter()
+                .map(|gate| {
+                    let inputs = gate
+                        .inputs
+                        .iter()
+                        .map(|net| net.map(|net| powered[net]).unwrap_or(false))
+                        .collect::<Vec<_>>();
+                    gate_output(gate.gate, &inputs)
+                })
+                .collect::<Vec<_>>();
+            if next == outputs {
+                return self.output_pins.iter().map(|&net| powered[net]).collect();
+            }
+            outputs = next;
+        }
+        panic!("Cell {} does not settle", cell.yosys_id());
+    }
+}
+
+// Values of all outputs of a combinational cell for every combination of
+// inputs (the first input is the most significant bit of the row).
+pub fn truth_table(cell: &dyn BasicCircuitDetails) -> Vec<Vec<bool>> {
+    let n = cell.input_names().len();
+    let simulation = Simulation::new(cell);
+    (0..1 << n)
+        .map(|row| {
+            let inputs = (0..n)
+                .map(|idx| row >> (n - 1 - idx) & 1 == 1)
+                .collect::<Vec<_>>();
+            simulation.run(cell, &inputs)
+        })
+        .collect()
+}
+
+// A product term, as the values of the inputs it depends on (`mask`).
+#[derive(Copy, Clone, PartialEq)]
+struct Implicant {
+    value: usize,
+    mask: usize,
+}
+
+impl Implicant {
+    fn covers(&self, row: usize) -> bool {
+        row & self.mask == self.value
+    }
+}
+
+// Minimal (or close to it) set of product terms covering the rows where the
+// truth table is true (Quine-McCluskey, picking the largest terms first).
+fn minimize(n: usize, truth_table: &[bool]) -> Vec<Implicant> {
+    let all = (1 << n) - 1;
+    let mut terms = (0..truth_table.len())
+        .filter(|&row| truth_table[row])
+        .map(|row| Implicant {
+            value: row,
+            mask: all,
+        })
+        .collect::<Vec<_>>();
+    let mut primes = Vec::new();
+    while !terms.is_empty() {
+        let mut merged = vec![false; terms.len()];
+        let mut next: Vec<Implicant> = Vec::new();
+        for a in 0..terms.len() {
+            for b in a + 1..terms.len() {
+                let diff = terms[a].value ^ terms[b].value;
+                if terms[a].mask == terms[b].mask && diff.count_ones() == 1 {
+                    merged[a] = true;
+                    merged[b] = true;
+                    let term = Implicant {
+                        value: terms[a].value & !diff,
+                        mask: terms[a].mask & !diff,
+                    };
+                    if !next.contains(&term) {
+                        next.push(term);
+                    }
+                }
+            }
+        }
+        primes.extend(
+            (0..terms.len())
+                .filter(|&idx| !merged[idx])
+                .map(|idx| terms[idx]),
+        );
+        terms = next;
+    }
+
+    let mut uncovered = (0..truth_table.len())
+        .filter(|&row| truth_table[row])
+        .collect::<Vec<_>>();
+    // Terms that are the only ones covering a row are always required.
+    let mut cover = Vec::new();
+    for &row in uncovered.iter() {
+        let covering = primes.iter().filter(|p| p.covers(row)).collect::<Vec<_>>();
+        if covering.len() == 1 && !cover.contains(covering[0]) {
+            cover.push(*covering[0]);
+        }
+    }
+    uncovered.retain(|&row| !cover.iter().any(|p| p.covers(row)));
+    while !uncovered.is_empty() {
+        let best = *primes
+            .iter()
+            .max_by_key(|p| {
+                let covered = uncovered.iter().filter(|&&row| p.covers(row)).count();
+                (covered, cmp::Reverse(p.mask.count_ones()))
+            })
+            .unwrap();
+        uncovered.retain(|&row| !best.covers(row));
+        cover.push(best);
+    }
+    cover.sort_by_key(|p| (cmp::Reverse(p.mask), cmp::Reverse(p.value)));
+    cover
+}
+
+// Sum of products, with the terms in parentheses.
+fn sum_of_products(names: &[&str], terms: &[Implicant]) -> Vec<String> {
+    let n = names.len();
+    terms
+        .iter()
+        .map(|term| {
+            let literals = names
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| term.mask >> (n - 1 - idx) & 1 == 1)
+                .map(|(idx, name)| {
+                    if term.value >> (n - 1 - idx) & 1 == 1 {
+                        name.to_string()
+                    } else {
+                        format!("{}'", name)
+                    }
+                })
+                .collect::<Vec<_>>();
+            if literals.len() == 1 {
+                literals[0].clone()
+            } else {
+                format!("({})", literals.join("*"))
+            }
+        })
+        .collect()
+}
+
+// Boolean function for the given truth table, indexed by the inputs (the first
+// input is the most significant bit). The shortest of the sum of products of
+// the function, of its complement and of an XOR of the inputs is used, e.g.
+// "(A*B)'" instead of "(A'*B')+(A'*B)+(A*B')".
+fn function(names: &[&str], truth_table: &[bool]) -> String {
+    if truth_table.iter().all(|&v| v) {
+        return "1".to_string();
+    }
+    if truth_table.iter().all(|&v| !v) {
+        return "0".to_string();
+    }
+    let n = names.len();
+
+    // XORs of two or more of the inputs, or their complement.
+    for mask in 1..1usize << n {
+        if mask.count_ones() < 2 {
+            continue;
+        }
+        let parity = |row: usize| (row & mask).count_ones() % 2 == 1;
+        let inverted = truth_table[0];
+        if (0..truth_table.len()).all(|row| truth_table[row] == (parity(row) != inverted)) {
+            let inputs = names
+                .iter()
+                .enumerate()
+                .filter(|(idx, _)| mask >> (n - 1 - idx) & 1 == 1)
+                .map(|(_, name)| *name)
+                .collect::<Vec<_>>();
+            let xor = format!("({})", inputs.join("^"));
+            return if inverted { format!("{}'", xor) } else { xor };
+        }
+    }
+
+    let direct = sum_of_products(names, &minimize(n, truth_table));
+    let direct = if direct.len() == 1 {
+        direct[0].clone()
+    } else {
+        format!("({})", direct.join("+"))
+    };
+
+    let complement = truth_table.iter().map(|&v| !v).collect::<Vec<_>>();
+    let complement = sum_of_products(names, &minimize(n, &complement));
+    let complement = if complement.len() == 1 {
+        format!("{}'", complement[0])
+    } else {
+        format!("({})'", complement.join("+"))
+    };
+
+    if complement.len() < direct.len() {
+        complement
+    } else {
+        direct
+    }
+}
+
+fn write_cell(out: &mut File, cell: &BasicCircuitYada) -> std::io::Result<()> {
+    writeln!(out, "  cell({}) {{", cell.yosys_id())?;
+    writeln!(out, "    area: {};", cell.width() * cell.height())?;
+    if let Some(state) = cell.liberty_state() {
+        writeln!(out, "    {}", state)?;
+    }
+    for (idx, name) in cell.input_names().iter().enumerate() {
+        if cell.clock_input() == Some(idx) && cell.liberty_state().unwrap().starts_with("ff") {
+            writeln!(
+                out,
+                "    pin({}) {{ direction: input; clock: true; }}",
+                name
+            )?;
+        } else {
+            writeln!(out, "    pin({}) {{ direction: input; }}", name)?;
+        }
+    }
+
+    let functions = if cell.liberty_state().is_some() {
+        vec!["IQ".to_string(); cell.output_names().len()]
+    } else {
+        let truth_table = truth_table(cell);
+        (0..cell.output_names().len())
+            .map(|idx| {
+                let column = truth_table.iter().map(|row| row[idx]).collect::<Vec<_>>();
+                function(cell.input_names(), &column)
+            })
+            .collect()
+    };
+    for (name, function) in cell.output_names().iter().zip(functions) {
+        writeln!(
+            out,
+            "    pin({}) {{ direction: output; function: \"{}\"; }}",
+            name, function
+        )?;
+    }
+    writeln!(out, "  }}")
+}
+
+pub fn write_liberty(filepath: &str, library: &CellLibrary) -> std::io::Result<()> {
+    let mut out = File::create(filepath)?;
+    writeln!(out, "library(demo) {{")?;
+    let builtin = BasicCircuitYada::cells()
+        .iter()
+        .filter(|c| library.get(c.yosys_id()).is_none())
+        .copied();
+    for cell in builtin.chain(library.cells()) {
+        write_cell(&mut out, &cell)?;
+    }
+    writeln!(out, "}}")
+}
@@ -14,6 +14,7 @@
 
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::collections::HashMap;
 
 // Structured - at least a little.
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
@@ -37,19 +38,328 @@ pub struct YosysJsonCell {
     pub cell_type: String,
     parameters: Value, // can be ignored
     attributes: Value, // can be ignored
-    pub connections: std::collections::HashMap<String, Vec<Value>>,
+    pub connections: HashMap<String, Vec<Value>>,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct YosysJsonModule {
     attributes: Value,
-    pub ports: std::collections::HashMap<String, YosysJsonPort>,
-    pub cells: std::collections::HashMap<String, YosysJsonCell>,
+    pub ports: HashMap<String, YosysJsonPort>,
+    pub cells: HashMap<String, YosysJsonCell>,
     netnames: Value,
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct YosysJson {
     creator: String,
-    pub modules: std::collections::HashMap<String, YosysJsonModule>,
+    pub modules: HashMap<String, YosysJsonModule>,
+}
+
+impl YosysJsonCell {
+    pub fn new(cell_type: &str, connections: HashMap<String, Vec<Value>>) -> Self {
+        Self {
+            hide_name: 0,
+            cell_type: cell_type.to_string(),
+            parameters: Value::Null,
+            attributes: Value::Null,
+            connections,
+        }
+    }
+}
+
+impl YosysJsonModule {
+    pub fn new(
+        ports: HashMap<String, YosysJsonPort>,
+        cells: HashMap<String, YosysJsonCell>,
+        top: bool,
+    ) -> Self {
+        let mut attributes = serde_json::Map::new();
+        if top {
+            attributes.insert("top".to_string(), Value::from(1));
+        }
+        Self {
+            attributes: Value::Object(attributes),
+            ports,
+            cells,
+            netnames: Value::Null,
+        }
+    }
+}
+
+impl YosysJson {
+    pub fn new(creator: &str, modules: HashMap<String, YosysJsonModule>) -> Self {
+        Self {
+            creator: creator.to_string(),
+            modules,
+        }
+    }
+}
+
+// Whether the given attribute is set. yosys writes attributes either as
+// numbers or as binary strings.
+fn has_attribute(attributes: &Value, name: &str) -> bool {
+    match attributes.get(name) {
+        Some(Value::Number(n)) => n.as_u64() != Some(0),
+        Some(Value::String(s)) => s.contains('1'),
+        _ => false,
+    }
+}
+
+impl YosysJsonModule {
+    fn is_blackbox(&self) -> bool {
+        has_attribute(&self.attributes, "blackbox")
+    }
+
+    fn bits(&self) -> impl Iterator<Item = &Value> {
+        self.ports.values().flat_map(|p| p.bits.iter()).chain(
+            self.cells
+                .values()
+                .flat_map(|c| c.connections.values().flatten()),
+        )
+    }
+}
+
+// State while copying the cells of all submodule instances into one module.
+struct Flattener<'a> {
+    modules: &'a HashMap<String, YosysJsonModule>,
+    cells: HashMap<String, YosysJsonCell>,
+    next_net: u64,
+    // Nets that turned out to be the same as another net or a constant, e.g.
+    // because a submodule directly connects an input to an output.
+    aliases: HashMap<u64, Value>,
+    // Keeps submodule instances as cells with one connection per bit.
+    keep_instances: bool,
+}
+
+impl<'a> Flattener<'a> {
+    // Maps a bit of a module to the net it is connected to in the flattened
+    // module. Nets internal to the module get a new net.
+    fn map_bit(&mut self, bits: &mut HashMap<u64, Value>, bit: &Value) -> Value {
+        match bit.as_u64() {
+            Some(n) => {
+                let next_net = &mut self.next_net;
+                bits.entry(n)
+                    .or_insert_with(|| {
+                        *next_net += 1;
+                        Value::from(*next_net)
+                    })
+                    .clone()
+            }
+            None => bit.clone(),
+        }
+    }
+
+    fn instantiate(
+        &mut self,
+        module_name: &str,
+        path: &str,
+        mut bits: HashMap<u64, Value>,
+        stack: &mut Vec<String>,
+    ) {
+        if stack.iter().any(|m| m == module_name) {
+            panic!("Module {} instantiates itself", module_name);
+        }
+        stack.push(module_name.to_string());
+
+        let modules = self.modules;
+        for (name, cell) in modules[module_name].cells.iter() {
+            let mut cell = cell.clone();
+            for bit in cell.connections.values_mut().flatten() {
+                *bit = self.map_bit(&mut bits, bit);
+            }
+
+            match modules.get(&cell.cell_type) {
+                Some(submodule) if !submodule.is_blackbox() && self.keep_instances => {
+                    // Unconnected outputs get new nets, inputs are undefined.
+                    for (port_name, port) in submodule.ports.iter() {
+                        if !cell.connections.contains_key(port_name) {
+                            let bits = port
+                                .bits
+                                .iter()
+                                .map(|_| match port.direction {
+                                    YosysJsonPortDirection::Input => Value::from("x"),
+                                    YosysJsonPortDirection::Output => {
+                                        self.next_net += 1;
+                                        Value::from(self.next_net)
+                                    }
+                                })
+                                .collect();
+                            cell.connections.insert(port_name.clone(), bits);
+                        }
+                    }
+                    cell.connections = cell
+                        .connections
+                        .into_iter()
+                        .flat_map(|(port_name, bits)| {
+                            let width = bits.len();
+                            bits.into_iter().enumerate().map(move |(idx, bit)| {
+                                (bit_name(&port_name, width, idx), vec![bit])
+                            })
+                        })
+                        .collect();
+                    self.cells.insert(format!("{}{}", path, name), cell);
+                }
+                Some(submodule) if !submodule.is_blackbox() => {
+                    // Inputs first, so that outputs that are directly
+                    // connected to an input become aliases.
+                    let mut ports = submodule.ports.iter().collect::<Vec<_>>();
+                    ports.sort_by_key(|(_, p)| p.direction == YosysJsonPortDirection::Output);
+
+                    let mut sub_bits = HashMap::new();
+                    for (port_name, port) in ports {
+                        // Unconnected ports get new nets inside the instance.
+                        let outer = match cell.connections.get(port_name) {
+                            Some(outer) => outer,
+                            None => continue,
+                        };
+                        if outer.len() != port.bits.len() {
+                            panic!("Width mismatch on port {} of {}{}", port_name, path, name);
+                        }
+                        for (inner, outer) in port.bits.iter().zip(outer.iter()) {
+                            match (inner.as_u64(), port.direction.clone()) {
+                                (Some(inner), _) if !sub_bits.contains_key(&inner) => {
+                                    sub_bits.insert(inner, outer.clone());
+                                }
+                                (_, YosysJsonPortDirection::Output) => {
+                                    // Driven by another port or a constant.
+                                    let driver = match inner.as_u64() {
+                                        Some(inner) => sub_bits[&inner].clone(),
+                                        None => inner.clone(),
+                                    };
+                                    if let Some(outer) = outer.as_u64() {
+                                        self.aliases.insert(outer, driver);
+                                    }
+                                }
+                                // Two inputs of the instance use the same net
+                                // internally, nothing to do.
+                                (_, YosysJsonPortDirection::Input) => {}
+                            }
+                        }
+                    }
+
+                    let path = format!("{}{}.", path, name);
+                    let cell_type = cell.cell_type.clone();
+                    self.instantiate(&cell_type, &path, sub_bits, stack);
+                }
+                _ => {
+                    self.cells.insert(format!("{}{}", path, name), cell);
+                }
+            }
+        }
+
+        stack.pop();
+    }
+
+    fn resolve(&self, bit: &mut Value) {
+        while let Some(alias) = bit.as_u64().and_then(|n| self.aliases.get(&n)) {
+            *bit = alias.clone();
+        }
+    }
+}
+
+// Name of a single bit of a port.
+pub fn bit_name(port_name: &str, width: usize, idx: usize) -> String {
+    if width == 1 {
+        port_name.to_string()
+    } else {
+        format!("{}[{}]", port_name, idx)
+    }
+}
+
+impl YosysJson {
+    // Whether the given cell type is a module of this file that can be
+    // instantiated.
+    pub fn is_module(&self, name: &str) -> bool {
+        matches!(self.modules.get(name), Some(m) if !m.is_blackbox())
+    }
+
+    // The module given by the user, the one marked as top module by yosys, or
+    // the only module that is not instantiated anywhere else.
+    pub fn top_module(&self, top: Option<&str>) -> &str {
+        if let Some(top) = top {
+            if !self.modules.contains_key(top) {
+                panic!("Top module {} not found", top);
+            }
+            return self.modules.get_key_value(top).unwrap().0;
+        }
+
+        if let Some((name, _)) = self
+            .modules
+            .iter()
+            .find(|(_, m)| has_attribute(&m.attributes, "top"))
+        {
+            return name;
+        }
+
+        let candidates = self
+            .modules
+            .iter()
+            .filter(|(name, m)| {
+                !m.is_blackbox()
+                    && !self
+                        .modules
+                        .values()
+                        .flat_map(|m| m.cells.values())
+                        .any(|c| &c.cell_type == *name)
+            })
+            .map(|(name, _)| name)
+            .collect::<Vec<_>>();
+        match candidates.as_slice() {
+            [name] => name,
+            [] => panic!("Input file contains no top module"),
+            _ => panic!(
+                "Input file contains several top modules ({:?}), choose one with --top",
+                candidates
+            ),
+        }
+    }
+
+    // Returns the given module with the cells of all submodules copied into
+    // it. Every instance gets its own nets and its cells are prefixed with the
+    // instance name, e.g. `alu.adder.$abc$123`. With `keep_instances`, the
+    // submodule instances are kept as cells instead, with one port per bit
+    // (see `bit_name`).
+    pub fn flatten(&self, top: &str, keep_instances: bool) -> YosysJsonModule {
+        let module = &self.modules[top];
+        let mut flattener = Flattener {
+            modules: &self.modules,
+            cells: HashMap::new(),
+            next_net: self
+                .modules
+                .values()
+                .flat_map(|m| m.bits())
+                .filter_map(|b| b.as_u64())
+                .max()
+                .unwrap_or(0),
+            aliases: HashMap::new(),
+            keep_instances,
+        };
+
+        // The nets of the top module stay the same.
+        let bits = module
+            .bits()
+            .filter_map(|b| b.as_u64())
+            .map(|n| (n, Value::from(n)))
+            .collect();
+        flattener.instantiate(top, "", bits, &mut Vec::new());
+
+        let mut cells = std::mem::take(&mut flattener.cells);
+        for bit in cells
+            .values_mut()
+            .flat_map(|c| c.connections.values_mut().flatten())
+        {
+            flattener.resolve(bit);
+        }
+        let mut ports = module.ports.clone();
+        for bit in ports.values_mut().flat_map(|p| p.bits.iter_mut()) {
+            flattener.resolve(bit);
+        }
+
+        YosysJsonModule {
+            attributes: module.attributes.clone(),
+            ports,
+            cells,
+            netnames: module.netnames.clone(),
+        }
+    }
 }
@@ -14,35 +14,63 @@
 
 #![feature(drain_filter)]
 
+mod blif;
 mod canvas;
 mod channel_router;
 mod circuit;
+mod clock;
+mod feedback;
 mod gate;
+mod liberty;
 mod loader;
+mod maze_router;
 mod placer;
+mod region;
+mod schedule;
+mod verilog;
 
 extern crate rayon;
 
+use crate::blif::parse_blif;
 use crate::canvas::*;
 use crate::channel_router::*;
 use crate::circuit::*;
-use crate::gate::BasicCircuitDetails;
+use crate::clock::{draw_clock_spines, find_clock_nets, report_clock_delays, ClockSource};
+use crate::feedback::route_feedback_nets;
+use crate::gate::{BasicCircuitDetails, CellLibrary};
+use crate::liberty::write_liberty;
 use crate::loader::*;
-use crate::placer::place_gates;
+use crate::maze_router::{route_nets, MazeNet};
+use crate::placer::{place_gates, place_gates_annealing, place_gates_barycenter, Placer};
+use crate::region::{region_macro, without_unused_inputs};
+use crate::schedule::{reschedule, Schedule};
+use crate::verilog::parse_verilog;
 use clap::{App, Arg};
 use core::convert::TryFrom;
 use rayon::prelude::*;
+use std::collections::{HashMap, HashSet};
 use std::fs::File;
 use std::io::Read; //, CircuitTypeT};
 
-fn resolve_gate_dependencies(
-    mut circuits: Vec<Circuit>,
-    output_ports: Vec<Port>,
-) -> Vec<Vec<Circuit>> {
+// Schedules every gate as soon as possible, see `schedule.rs` for other
+// strategies.
+fn resolve_gate_dependencies(circuits: Vec<Circuit>, output_ports: Vec<Port>) -> Vec<Vec<Circuit>> {
     let mut gate_hierarchy = Vec::new();
     let mut nets_available = Vec::new();
     let mut required_nets = Vec::new();
 
+    // Sequential gates break all loops: their outputs are available right
+    // away (from feedback pins in the first level) and their inputs are only
+    // required in the last level, next to the output pins.
+    let (sequential_gates, mut circuits): (Vec<Circuit>, Vec<Circuit>) = circuits
+        .into_iter()
+        .partition(|c| c.basic_circuit.is_sequential());
+    for g in sequential_gates.iter() {
+        for o in g.outputs.iter() {
+            circuits.push(Circuit::new_feedback_pin(*o));
+        }
+    }
+
     while !circuits.is_empty() {
         // Find circuits where all inputs are satisfied.
         let mut placable_gates: Vec<Circuit> = circuits
@@ -81,10 +109,24 @@ fn resolve_gate_dependencies(
         gate_hierarchy.push(placable_gates);
     }
 
+    for g in sequential_gates.iter() {
+        for i in g.inputs.iter() {
+            if let PortConnection::Net(net) = i.connection {
+                if !nets_available.contains(&net) {
+                    panic!("[!] Net {} is never driven", net);
+                }
+                if !required_nets.contains(&net) {
+                    required_nets.push(net);
+                }
+            }
+        }
+    }
+
     gate_hierarchy.push(
         output_ports
             .iter()
             .map(|&bit| Circuit::new_external_output_pin(bit))
+            .chain(sequential_gates)
             .collect(),
     );
 
@@ -97,9 +139,22 @@ fn resolve_gate_dependencies(
         })
         .collect::<Vec<_>>();
 
+    // Gates with several outputs (e.g. adders) may leave some of them unused,
+    // as long as any of their other outputs is used.
+    let is_used = |net: &usize| required_nets.contains(net) || output_nets.contains(net);
+    let spare_nets = gate_hierarchy
+        .iter()
+        .flatten()
+        .filter(|c| c.outputs.len() > 1)
+        .map(|c| c.outputs.iter().filter_map(|o| o.connection.get_net()))
+        .filter(|outputs| outputs.clone().any(|net| is_used(&net)))
+        .flatten()
+        .filter(|net| !is_used(net))
+        .collect::<HashSet<_>>();
+
     for n in nets_available
         .iter()
-        .filter(|v| !required_nets.contains(v) && !output_nets.contains(v))
+        .filter(|v| !is_used(v) && !spare_nets.contains(v))
     {
         panic!("[!] Net {} seems to be not used - bug?", n);
     }
@@ -107,22 +162,31 @@ fn resolve_gate_dependencies(
     gate_hierarchy
 }
 
-fn parse_json(filepath: &str) -> std::io::Result<Vec<Vec<Circuit>>> {
+fn parse_json(filepath: &str) -> std::io::Result<YosysJson> {
     let mut file = File::open(filepath)?;
     let mut buf = String::new();
     file.read_to_string(&mut buf)?;
 
-    let v: YosysJson = serde_json::from_str(&*buf)?;
-    if v.modules.len() != 1 {
-        panic!("Input file contained no or more than one module");
-    }
-    let m = &v.modules.values().next().unwrap();
+    Ok(serde_json::from_str(&*buf)?)
+}
 
+fn parse_module(m: &YosysJsonModule, library: &CellLibrary) -> Vec<Vec<Circuit>> {
     // Convert cells to `Circuit`s.
     let mut circuits: Vec<_> = m
         .cells
         .values()
-        .map(|v| Circuit::try_from(v).unwrap_or_else(|_| panic!("Could not convert {:?}", v)))
+        .map(|v| match library.get(&v.cell_type) {
+            Some(basic_circuit) => Circuit::from_cell(basic_circuit, v),
+            None => Circuit::try_from(v).unwrap_or_else(|_| {
+                if v.cell_type.starts_with("$_") {
+                    panic!(
+                        "Could not convert {:?}, map it using `dfflibmap -liberty mycells.lib`",
+                        v
+                    )
+                }
+                panic!("Could not convert {:?}", v)
+            }),
+        })
         .collect();
 
     // Add input connections.
@@ -156,84 +220,89 @@ fn parse_json(filepath: &str) -> std::io::Result<Vec<Vec<Circuit>>> {
     );
 
     println!("[*] Calculating gate layout.");
-    Ok(resolve_gate_dependencies(circuits, output_pins))
+    resolve_gate_dependencies(circuits, output_pins)
 }
 
-fn main() -> std::io::Result<()> {
-    let parameters = App::new("Minetest HDL")
-        .version("0.1")
-        .author("Kevin Hamacher <hamacher@google.com>")
-        .about("Converts synthesized circuit (yosys json output) to a minetest schematic that can be placed in minetest")
-        .arg(
-            Arg::with_name("text")
-                .short("t")
-                .long("text")
-                .help("Print text overview on STDOUT"),
-        )
-        .arg(
-            Arg::with_name("write_lua")
-                .short("l")
-                .long("write_lua")
-                .help("Writes a lua blueprint")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("write_mts")
-                .short("m")
-                .long("write_mts")
-                .help("Writes a MTS blueprint (binary format)")
-                .takes_value(true),
-        )
-        .arg(
-            Arg::with_name("INPUT")
-                .help("Sets the input file to use")
-                .required(true)
-                .index(1),
-        )
-        .get_matches();
-
-    let lua_filename = parameters.value_of("write_lua");
-    let mts_filename = parameters.value_of("write_mts");
-
-    let mut gate_hierarchy = parse_json(parameters.value_of("INPUT").unwrap())?;
+// Adds 'forwarding' gates to every gate group that does not provide a net
+// required by the next group, if `forward` returns true for this net.
+fn add_forwarding_gates<F: Fn(usize) -> bool>(gate_hierarchy: &mut Vec<Vec<Circuit>>, forward: F) {
+    // Starting with 2 here since we will always have all inputs at the 1st
+    // stage (0th = input bits for the whole circuitry), so we need to start
+    // checking that the 1st stage will 'reexport' the required bits.
+    for idx in (2..gate_hierarchy.len()).rev() {
+        let required_inputs: Vec<_> = gate_hierarchy[idx]
+            .iter()
+            .flat_map(|c| c.inputs.iter().map(|i| i.connection))
+            .filter(|v| !v.is_constant())
+            .map(|v| v.get_net().unwrap())
+            .collect();
 
-    println!("[*] Adding 'forwarding' gates to keep unused nets.");
-    {
-        // Starting with 2 here since we will always have all inputs at the 1st
-        // stage (0th = input bits for the whole circuitry), so we need to start
-        // checking that the 1st stage will 'reexport' the required bits.
-        for idx in (2..gate_hierarchy.len()).rev() {
-            let required_inputs: Vec<_> = gate_hierarchy[idx]
-                .iter()
-                .flat_map(|c| c.inputs.iter().map(|i| i.connection))
-                .filter(|v| !v.is_constant())
-                .map(|v| v.get_net().unwrap())
-                .collect();
+        let mut outputs_available: Vec<_> = gate_hierarchy[idx - 1]
+            .iter()
+            .flat_map(|c| c.outputs.iter().map(|o| o.connection))
+            .filter(|v| !v.is_constant())
+            .map(|v| v.get_net().unwrap())
+            .collect();
 
-            let mut outputs_available: Vec<_> = gate_hierarchy[idx - 1]
-                .iter()
-                .flat_map(|c| c.outputs.iter().map(|o| o.connection))
-                .filter(|v| !v.is_constant())
-                .map(|v| v.get_net().unwrap())
-                .collect();
-
-            for ri in required_inputs {
-                if !outputs_available.contains(&ri) {
-                    // The previous segment did not provide the required output,
-                    // so add a dependency.
-                    gate_hierarchy[idx - 1].push(Circuit::new_forwarding_pin(Port::new_unplaced(
-                        PortConnection::Net(ri),
-                    )));
-                    outputs_available.push(ri);
-                }
+        for ri in required_inputs.into_iter().filter(|&net| forward(net)) {
+            if !outputs_available.contains(&ri) {
+                // The previous segment did not provide the required output,
+                // so add a dependency.
+                gate_hierarchy[idx - 1].push(Circuit::new_forwarding_pin(Port::new_unplaced(
+                    PortConnection::Net(ri),
+                )));
+                outputs_available.push(ri);
             }
         }
     }
+}
 
+// Restricts the channel layout to the nets that are present on both sides, all
+// others are left to the maze router. Clock nets are left to the clock spines.
+fn channel_layouts(
+    gate_hierarchy: &[Vec<Circuit>],
+    gategroup_idx: usize,
+    clock_nets: &HashSet<usize>,
+) -> (Box<ChannelLayout>, Box<ChannelLayout>) {
+    let mut channel_layout =
+        determine_channel_layout(gate_hierarchy[gategroup_idx].iter(), IOType::Output);
+    // Determine required channel layout (input pins of the next group).
+    let mut desired_channel_layout =
+        determine_channel_layout(gate_hierarchy[gategroup_idx + 1].iter(), IOType::Input);
+
+    let is_clock =
+        |v: &ChannelState| matches!(v, ChannelState::Net(net) if clock_nets.contains(net));
+    let start = channel_layout.to_vec();
+    for v in channel_layout.iter_mut() {
+        if v.contains_net() && (!desired_channel_layout.contains(v) || is_clock(v)) {
+            *v = ChannelState::Occupied;
+        }
+    }
+    for v in desired_channel_layout.iter_mut() {
+        if v.contains_net() && (!start.contains(v) || is_clock(v)) {
+            *v = ChannelState::Occupied;
+        }
+    }
+    (channel_layout, desired_channel_layout)
+}
+
+// Places and routes all gates, returns the canvas and the nets that still need
+// to be connected by the maze router.
+fn place_and_route(
+    gate_hierarchy: &mut Vec<Vec<Circuit>>,
+    placer: Placer,
+    router: Router,
+    layers: usize,
+    clock_nets: &HashSet<usize>,
+) -> Result<(Canvas, Vec<MazeNet>), String> {
     println!("[*] Performing channel routing.");
 
-    // Place the first circuit block.
-    let mut block_x_start = 0;
+    // Place the first circuit block, leaving room for the feedback wires on
+    // the left.
+    let mut block_x_start = gate_hierarchy[0]
+        .iter()
+        .filter(|c| c.basic_circuit.is_feedback())
+        .count() as u32;
     {
         let mut gate_y = 0;
         let mut max_w = 0;
@@ -271,49 +340,148 @@ fn main() -> std::io::Result<()> {
         // Determine required channel layout (input pins of the next group).
         place_gates(&channel_layout, &mut gate_hierarchy[gategroup_idx + 1]);
     }
+    match placer {
+        Placer::Simple => {}
+        Placer::Annealing(seed) => {
+            println!("[*] Improving placement by simulated annealing");
+            place_gates_annealing(gate_hierarchy, seed);
+        }
+        Placer::Barycenter => {
+            println!("[*] Reordering gates to reduce wire crossings");
+            place_gates_barycenter(gate_hierarchy);
+        }
+    }
 
     println!("[*] Routing");
     let ops_per_step = (0..gate_hierarchy.len() - 1)
         .into_par_iter()
         .map(|gategroup_idx| {
-            let channel_layout =
-                determine_channel_layout(gate_hierarchy[gategroup_idx].iter(), IOType::Output);
-            // Determine required channel layout (input pins of the next group).
-            let desired_channel_layout =
-                determine_channel_layout(gate_hierarchy[gategroup_idx + 1].iter(), IOType::Input);
-            route_channel(&channel_layout, &desired_channel_layout)
+            let (channel_layout, desired_channel_layout) =
+                channel_layouts(&gate_hierarchy, gategroup_idx, clock_nets);
+            router.route(&channel_layout, &desired_channel_layout, layers)
         })
         .collect::<Vec<_>>();
+    if let Router::Negotiated(max_width) = router {
+        let failed = ops_per_step
+            .iter()
+            .enumerate()
+            .filter(|(_, ops)| ops.is_none())
+            .map(|(idx, _)| idx)
+            .collect::<Vec<_>>();
+        for &idx in failed.iter() {
+            let (channel_layout, desired_channel_layout) =
+                channel_layouts(&gate_hierarchy, idx, clock_nets);
+            println!(
+                "[!] Channel {} does not fit into {} steps, it can be routed in {} steps",
+                idx + 1,
+                max_width,
+                min_channel_width(&channel_layout, &desired_channel_layout)
+            );
+        }
+        if !failed.is_empty() {
+            return Err(format!(
+                "{} channel(s) do not fit into {} steps, increase --max_channel_width",
+                failed.len(),
+                max_width
+            ));
+        }
+    }
+    let ops_per_step = ops_per_step
+        .into_iter()
+        .map(Option::unwrap)
+        .collect::<Vec<_>>();
+    println!(
+        " [+] {} routing steps in total",
+        ops_per_step.iter().map(|ops| ops.len()).sum::<usize>()
+    );
 
     let mut canvas = Canvas::new();
     let mut place_constants_here = Vec::new();
+    let mut maze_sources = HashMap::new();
+    let mut maze_sinks = Vec::new();
+    let mut clock_sources: Vec<ClockSource> = Vec::new();
+    let mut tap_x = 0;
     println!("[*] Drawing to canvas");
     for (gategroup_idx, ops) in ops_per_step.iter().enumerate() {
-        let channel_layout =
-            determine_channel_layout(gate_hierarchy[gategroup_idx].iter(), IOType::Output);
-        // Determine required channel layout (input pins of the next group).
-        let desired_channel_layout =
-            determine_channel_layout(gate_hierarchy[gategroup_idx + 1].iter(), IOType::Input);
+        let (channel_layout, desired_channel_layout) =
+            channel_layouts(&gate_hierarchy, gategroup_idx, clock_nets);
 
         // Let's draw our channels.
         // 1 pixel initial wires
         const WIRE_LENGTH_AFTER_GATE: usize = 1;
         let mut x = block_x_start;
-        for xi in 0..WIRE_LENGTH_AFTER_GATE {
+        for o in gate_hierarchy[gategroup_idx]
+            .iter()
+            .flat_map(|c| &c.outputs)
+        {
+            if let PortConnection::Net(net) = o.connection {
+                let source = (x as usize, o.position.unwrap().1 as usize);
+                maze_sources.insert(net, source);
+                // Every clock net drops down to its spine in a column of its
+                // own, right after the initial wires.
+                if clock_nets.contains(&net) {
+                    clock_sources.push(ClockSource {
+                        net,
+                        source,
+                        drop_x: x as usize
+                            + WIRE_LENGTH_AFTER_GATE
+                            + clock_sources
+                                .iter()
+                                .filter(|s| s.source.0 == source.0)
+                                .count(),
+                    });
+                }
+            }
+        }
+        let wire_length = WIRE_LENGTH_AFTER_GATE
+            + clock_sources
+                .iter()
+                .filter(|s| s.source.0 == x as usize)
+                .count();
+        for xi in 0..wire_length {
             for (ly, cly) in channel_layout.iter().enumerate() {
                 if cly.contains_net() {
                     canvas.set(x as usize + xi, ly as usize, BlockType::WireH);
                 }
             }
         }
-        x += WIRE_LENGTH_AFTER_GATE as u32;
+        x += wire_length as u32;
 
         canvas.set_channel_wires(&ops, &mut x);
 
+        // Reserve a tap column for every clock net in front of the last
+        // gate column.
+        if gategroup_idx + 2 == gate_hierarchy.len() {
+            tap_x = x as usize;
+            for _ in 0..clock_nets.len() {
+                for (ly, dly) in desired_channel_layout.iter().enumerate() {
+                    if dly.contains_net() {
+                        canvas.set(x as usize, ly, BlockType::WireH);
+                    }
+                }
+                x += 1;
+            }
+        }
+
         for c in gate_hierarchy[gategroup_idx + 1].iter_mut() {
             c.reposition(x);
         }
 
+        // Inputs that are not connected by the channel router.
+        for i in gate_hierarchy[gategroup_idx + 1]
+            .iter()
+            .flat_map(|c| &c.inputs)
+        {
+            let p = i.position.unwrap();
+            if let PortConnection::Net(net) = i.connection {
+                if desired_channel_layout[p.1 as usize] != ChannelState::Net(net)
+                    && !clock_nets.contains(&net)
+                {
+                    maze_sinks.push((net, (p.0 as usize, p.1 as usize)));
+                }
+            }
+        }
+
         // Place constant inputs
         for pos in desired_channel_layout
             .iter()
@@ -339,13 +507,16 @@ fn main() -> std::io::Result<()> {
                 .position
                 .unwrap_or_else(|| panic!("Circuit {:#?} was not placed!", g));
             g.draw(&mut canvas);
-            for dx in g.width()..widest_gate {
-                let p = gate_pos;
-                canvas.set(
-                    p.0 as usize + dx as usize,
-                    p.1 as usize + g.basic_circuit.output_y_offset(0),
-                    BlockType::WireH,
-                );
+            // Extend all outputs to the widest gate of the column.
+            for idx in 0..g.outputs.len() {
+                for dx in g.width()..widest_gate {
+                    let p = gate_pos;
+                    canvas.set(
+                        p.0 as usize + dx as usize,
+                        p.1 as usize + g.basic_circuit.output_y_offset(idx),
+                        BlockType::WireH,
+                    );
+                }
             }
         }
     }
@@ -354,7 +525,353 @@ fn main() -> std::io::Result<()> {
         canvas.set(*x, *y, BlockType::Constant);
     }
 
-    println!("[*] Canvas dimensions: {:?}", canvas.dimensions());
+    draw_clock_spines(&mut canvas, clock_sources, tap_x, gate_hierarchy);
+    route_feedback_nets(&mut canvas, gate_hierarchy);
+
+    let mut maze_nets: Vec<MazeNet> = Vec::new();
+    for (net, sink) in maze_sinks {
+        match maze_nets.iter_mut().find(|n| n.net == net) {
+            Some(maze_net) => maze_net.sinks.push(sink),
+            None => maze_nets.push(MazeNet {
+                net,
+                source: maze_sources[&net],
+                sinks: vec![sink],
+            }),
+        }
+    }
+    Ok((canvas, maze_nets))
+}
+
+struct Options {
+    schedule: Schedule,
+    placer: Placer,
+    router: Router,
+    layers: usize,
+    use_maze_router: bool,
+    clock_spine: bool,
+}
+
+// Schedules, places and routes the gates. Returns the canvas and the placed
+// gates.
+fn build_canvas(
+    gate_hierarchy: Vec<Vec<Circuit>>,
+    options: &Options,
+) -> Result<(Canvas, Vec<Vec<Circuit>>), String> {
+    println!("[*] Scheduling gates");
+    for &name in &["asap", "alap", "balanced"] {
+        let mut gate_hierarchy =
+            reschedule(gate_hierarchy.clone(), Schedule::try_from(name).unwrap());
+        let gates = gate_hierarchy.iter().map(|g| g.len()).sum::<usize>();
+        add_forwarding_gates(&mut gate_hierarchy, |_| true);
+        println!(
+            " [+] {}: {} 'forwarding' gates",
+            name,
+            gate_hierarchy.iter().map(|g| g.len()).sum::<usize>() - gates
+        );
+    }
+    let gate_hierarchy = reschedule(gate_hierarchy, options.schedule);
+    report_clock_delays(&gate_hierarchy);
+
+    let clock_nets = if options.clock_spine {
+        find_clock_nets(&gate_hierarchy)
+    } else {
+        HashSet::new()
+    };
+
+    let mut forwarded_nets = HashSet::new();
+    loop {
+        let mut gate_hierarchy = gate_hierarchy.clone();
+        println!("[*] Adding 'forwarding' gates to keep unused nets.");
+        add_forwarding_gates(&mut gate_hierarchy, |net| {
+            !clock_nets.contains(&net)
+                && (!options.use_maze_router || forwarded_nets.contains(&net))
+        });
+
+        let (mut canvas, maze_nets) = place_and_route(
+            &mut gate_hierarchy,
+            options.placer,
+            options.router,
+            options.layers,
+            &clock_nets,
+        )?;
+        if maze_nets.is_empty() {
+            return Ok((canvas, gate_hierarchy));
+        }
+
+        println!("[*] Maze routing {} nets", maze_nets.len());
+        let failed = route_nets(&mut canvas, maze_nets);
+        if failed.is_empty() {
+            return Ok((canvas, gate_hierarchy));
+        }
+        // Clock nets and nets that were forwarded already can't be helped by
+        // forwarding them (again).
+        if failed
+            .iter()
+            .all(|net| clock_nets.contains(net) || forwarded_nets.contains(net))
+        {
+            return Err(format!("Could not route the nets {:?}", failed));
+        }
+        println!(
+            "[!] Could not route {} nets, adding 'forwarding' gates for them",
+            failed.len()
+        );
+        forwarded_nets.extend(failed);
+    }
+}
+
+fn error(msg: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, msg)
+}
+
+// Places and routes every submodule instantiated by `module` as a region of
+// its own, unless that was already done for another instance.
+fn build_regions(
+    json: &YosysJson,
+    module: &YosysJsonModule,
+    library: &mut CellLibrary,
+    options: &Options,
+) -> std::io::Result<()> {
+    for cell in module.cells.values() {
+        let name = &cell.cell_type;
+        if !json.is_module(name) || library.get(name).is_some() {
+            continue;
+        }
+
+        let submodule = json.flatten(name, true);
+        build_regions(json, &submodule, library, options)?;
+        println!("[*] Building region {}", name);
+        let gate_hierarchy = parse_module(&without_unused_inputs(&submodule), library);
+        let (canvas, gate_hierarchy) = build_canvas(gate_hierarchy, options).map_err(error)?;
+        let region = region_macro(name, &submodule, canvas, &gate_hierarchy).map_err(error)?;
+        let region = library.add_hard_macro(region);
+        println!(
+            " [+] Region {} is {}x{}",
+            name,
+            region.width(),
+            region.height()
+        );
+    }
+    Ok(())
+}
+
+fn main() -> std::io::Result<()> {
+    let parameters = App::new("Minetest HDL")
+        .version("0.1")
+        .author("Kevin Hamacher <hamacher@google.com>")
+        .about("Converts synthesized circuit (yosys json output) to a minetest schematic that can be placed in minetest")
+        .arg(
+            Arg::with_name("text")
+                .short("t")
+                .long("text")
+                .help("Print text overview on STDOUT"),
+        )
+        .arg(
+            Arg::with_name("write_lua")
+                .short("l")
+                .long("write_lua")
+                .help("Writes a lua blueprint")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("write_mts")
+                .short("m")
+                .long("write_mts")
+                .help("Writes a MTS blueprint (binary format)")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("layers")
+                .long("layers")
+                .help("Number of layers the channel router may place wires on")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("router")
+                .long("router")
+                .help("Channel router to use")
+                .possible_values(&["eviction", "greedy", "left_edge", "best", "negotiated"])
+                .default_value("eviction"),
+        )
+        .arg(
+            Arg::with_name("max_channel_width")
+                .long("max_channel_width")
+                .help("Maximum number of steps per channel for the negotiated router. Routing takes longer the larger this is: every iteration reroutes each net in O(steps * rows^2) for the rows between its pins")
+                .takes_value(true)
+                .required_if("router", "negotiated"),
+        )
+        .arg(
+            Arg::with_name("schedule")
+                .long("schedule")
+                .help("Assigns gates to columns as soon as possible, as late as possible or balanced to need fewer 'forwarding' gates")
+                .possible_values(&["asap", "alap", "balanced"])
+                .default_value("asap"),
+        )
+        .arg(
+            Arg::with_name("placer")
+                .long("placer")
+                .help("Placer to use")
+                .possible_values(&["simple", "annealing", "barycenter"])
+                .default_value("simple"),
+        )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .help("Seed for the annealing placer, the same seed gives the same placement")
+                .default_value("0"),
+        )
+        .arg(
+            Arg::with_name("maze_routing")
+                .long("maze_routing")
+                .help("Connects nets that skip gate columns using a maze router instead of 'forwarding' gates"),
+        )
+        .arg(
+            Arg::with_name("clock_spine")
+                .long("clock_spine")
+                .help("Routes the clock nets of flip-flops on spines below the circuit instead of through the channels"),
+        )
+        .arg(
+            Arg::with_name("bridge_crossings")
+                .long("bridge_crossings")
+                .help("Replaces wire crossings by vias to a second layer"),
+        )
+        .arg(
+            Arg::with_name("top")
+                .long("top")
+                .help("Sets the top module if the input file contains several modules")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("keep_hierarchy")
+                .long("keep_hierarchy")
+                .help("Places every submodule instance as a rectangular region of its own, instances of the same module share their layout"),
+        )
+        .arg(
+            Arg::with_name("cell_library")
+                .long("cell_library")
+                .help("Loads additional cells from a JSON cell library")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("hard_macro")
+                .long("hard_macro")
+                .help("Loads a hard macro from a MTS file, its pins are read from the JSON file next to it")
+                .takes_value(true)
+                .multiple(true)
+                .number_of_values(1),
+        )
+        .arg(
+            Arg::with_name("write_liberty")
+                .long("write_liberty")
+                .help("Writes a Liberty file describing all available cells and exits")
+                .takes_value(true),
+        )
+        .arg(
+            Arg::with_name("INPUT")
+                .help("Sets the input file to use (yosys JSON, BLIF or a Verilog netlist)")
+                .required_unless("write_liberty")
+                .index(1),
+        )
+        .get_matches();
+
+    let lua_filename = parameters.value_of("write_lua");
+    let mts_filename = parameters.value_of("write_mts");
+    let layers = parameters
+        .value_of("layers")
+        .map(|v| v.parse::<usize>().expect("Invalid number of layers"))
+        .unwrap_or(1);
+    if layers == 0 {
+        panic!("At least one layer is required");
+    }
+    let router = Router::new(
+        parameters.value_of("router").unwrap(),
+        parameters.value_of("max_channel_width"),
+    )
+    .unwrap_or_else(|e| panic!("{}", e));
+    if layers > 1 && matches!(router, Router::Negotiated(_)) {
+        panic!("The negotiated router only routes on one layer, use --layers 1");
+    }
+    let schedule = Schedule::try_from(parameters.value_of("schedule").unwrap()).unwrap();
+    let placer = match parameters.value_of("placer").unwrap() {
+        "annealing" => Placer::Annealing(
+            parameters
+                .value_of("seed")
+                .unwrap()
+                .parse::<u64>()
+                .expect("Invalid seed"),
+        ),
+        "barycenter" => Placer::Barycenter,
+        _ => Placer::Simple,
+    };
+
+    let mut library = match parameters.value_of("cell_library") {
+        Some(f) => {
+            let library = CellLibrary::load(f)?;
+            println!("[*] Loaded {} cells from {}", library.n_cells(), f);
+            library
+        }
+        None => CellLibrary::default(),
+    };
+    for f in parameters.values_of("hard_macro").into_iter().flatten() {
+        let hard_macro = library.load_hard_macro(f)?;
+        println!(
+            "[*] Loaded hard macro {} ({}x{}) from {}",
+            hard_macro.yosys_id(),
+            hard_macro.width(),
+            hard_macro.height(),
+            f
+        );
+    }
+
+    if let Some(f) = parameters.value_of("write_liberty") {
+        println!("[*] Generating Liberty file");
+        return write_liberty(f, &library);
+    }
+
+    let options = Options {
+        schedule,
+        placer,
+        router,
+        layers,
+        use_maze_router: parameters.occurrences_of("maze_routing") > 0,
+        clock_spine: parameters.occurrences_of("clock_spine") > 0,
+    };
+
+    let input = parameters.value_of("INPUT").unwrap();
+    let json = if input.ends_with(".blif") {
+        parse_blif(input, &library)?
+    } else if input.ends_with(".v") {
+        parse_verilog(input, &library)?
+    } else {
+        parse_json(input)?
+    };
+    let top = json.top_module(parameters.value_of("top"));
+    let keep_hierarchy = parameters.occurrences_of("keep_hierarchy") > 0;
+    if json.modules.len() > 1 && !keep_hierarchy {
+        println!("[*] Flattening top module {}", top);
+    }
+    let module = json.flatten(top, keep_hierarchy);
+    if keep_hierarchy {
+        build_regions(&json, &module, &mut library, &options)?;
+    }
+
+    println!("[*] Building top module {}", top);
+    let (mut canvas, _) = build_canvas(parse_module(&module, &library), &options).map_err(error)?;
+
+    if parameters.occurrences_of("bridge_crossings") > 0 {
+        println!("[*] Moving wire crossings to the next layer");
+        let n = canvas.bridge_crossings();
+        println!(" [+] Bridged {} crossings", n);
+    }
+
+    println!(
+        "[*] Canvas dimensions: {:?}, {} layer(s)",
+        canvas.dimensions(),
+        canvas.layers()
+    );
+    println!(
+        "[*] {} wire crossings",
+        canvas.count(BlockType::WireCrossing)
+    );
     if parameters.occurrences_of("text") > 0 {
         println!("*** text overview ***");
         canvas.draw();
@@ -0,0 +1,305 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// A* maze router working on layer 0 of the whole canvas.
+//
+// It is used for nets that skip one or more gate columns, so that they don't
+// need a chain of forwarding gates. Wires may only be placed on free blocks or
+// cross existing straight wires at a right angle.
+
+use crate::canvas::{BlockType, Canvas, CornerOrientation, TRotation};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+
+const STEP_COST: usize = 1;
+const CORNER_COST: usize = 4;
+const CROSSING_COST: usize = 8;
+// Number of rows below the circuit that may be used for routing.
+const MARGIN: usize = 32;
+
+const LEFT: u8 = 1;
+const RIGHT: u8 = 2;
+const UP: u8 = 4;
+const DOWN: u8 = 8;
+const DIRECTIONS: [u8; 4] = [LEFT, RIGHT, UP, DOWN];
+
+// A position and the direction the wire moved in to reach it.
+type Node = ((usize, usize), u8);
+
+fn opposite(dir: u8) -> u8 {
+    match dir {
+        LEFT => RIGHT,
+        RIGHT => LEFT,
+        UP => DOWN,
+        DOWN => UP,
+        _ => unreachable!(),
+    }
+}
+
+fn is_horizontal(dir: u8) -> bool {
+    dir == LEFT || dir == RIGHT
+}
+
+fn step(pos: (usize, usize), dir: u8) -> Option<(usize, usize)> {
+    match dir {
+        LEFT if pos.0 > 0 => Some((pos.0 - 1, pos.1)),
+        RIGHT => Some((pos.0 + 1, pos.1)),
+        UP if pos.1 > 0 => Some((pos.0, pos.1 - 1)),
+        DOWN => Some((pos.0, pos.1 + 1)),
+        _ => None,
+    }
+}
+
+// Returns the wire block connecting to all of the given directions.
+fn wire_block(dirs: u8) -> BlockType {
+    use BlockType::*;
+    match dirs {
+        d if d == LEFT | RIGHT => WireH,
+        d if d == UP | DOWN => WireV,
+        d if d == LEFT | UP => WireCorner(CornerOrientation::LeftUp),
+        d if d == LEFT | DOWN => WireCorner(CornerOrientation::LeftDown),
+        d if d == DOWN | RIGHT => WireCorner(CornerOrientation::DownRight),
+        d if d == UP | RIGHT => WireCorner(CornerOrientation::UpRight),
+        d if d == LEFT | RIGHT | DOWN => WireT(TRotation::LeftRightDown),
+        d if d == LEFT | RIGHT | UP => WireT(TRotation::LeftRightUp),
+        d if d == RIGHT | UP | DOWN => WireT(TRotation::RightUpDown),
+        d if d == LEFT | UP | DOWN => WireT(TRotation::LeftUpDown),
+        d if d == LEFT | RIGHT | UP | DOWN => WireStar,
+        _ => unreachable!("Dangling wire"),
+    }
+}
+
+pub struct MazeNet {
+    pub net: usize,
+    // Block next to the output pin, the wire enters it from the left.
+    pub source: (usize, usize),
+    // Blocks next to the input pins, the wire leaves them to the right.
+    pub sinks: Vec<(usize, usize)>,
+}
+
+impl MazeNet {
+    fn length(&self) -> usize {
+        self.sinks
+            .iter()
+            .map(|s| s.0.abs_diff(self.source.0) + s.1.abs_diff(self.source.1))
+            .sum()
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Passage {
+    Free,
+    Crossing,
+    Blocked,
+}
+
+struct MazeRouter<'a> {
+    canvas: &'a Canvas,
+    // Pins of all nets, nobody else may use them.
+    reserved: &'a HashSet<(usize, usize)>,
+    // Blocks of the net that is currently routed.
+    tree: HashMap<(usize, usize), u8>,
+    crossings: HashSet<(usize, usize)>,
+    width: usize,
+    height: usize,
+}
+
+impl<'a> MazeRouter<'a> {
+    // Vias and constants connect to all of their neighbours.
+    fn is_isolated(&self, pos: (usize, usize)) -> bool {
+        DIRECTIONS.iter().filter_map(|&d| step(pos, d)).all(|n| {
+            !matches!(
+                self.canvas.get(n.0, n.1),
+                BlockType::Via | BlockType::Constant
+            )
+        })
+    }
+
+    fn passage(&self, pos: (usize, usize), dir: u8, target: (usize, usize)) -> Passage {
+        if pos.0 >= self.width
+            || pos.1 >= self.height
+            || self.tree.contains_key(&pos)
+            || self.crossings.contains(&pos)
+            || (pos != target && self.reserved.contains(&pos))
+        {
+            return Passage::Blocked;
+        }
+
+        match self.canvas.get(pos.0, pos.1) {
+            BlockType::Air if self.is_isolated(pos) => Passage::Free,
+            BlockType::WireH if !is_horizontal(dir) && pos != target => Passage::Crossing,
+            BlockType::WireV if is_horizontal(dir) => Passage::Crossing,
+            _ => Passage::Blocked,
+        }
+    }
+
+    // Connects `target` to the current tree, the wire has to leave it to the right.
+    fn route(&mut self, target: (usize, usize)) -> bool {
+        let heuristic = |pos: (usize, usize)| target.0.abs_diff(pos.0) + target.1.abs_diff(pos.1);
+
+        let mut queue = BinaryHeap::new();
+        let mut costs = HashMap::new();
+        // (position, direction) -> previous position
+        let mut previous = HashMap::new();
+
+        for (&pos, &dirs) in self.tree.iter() {
+            for &dir in DIRECTIONS.iter().filter(|&&d| dirs & d == 0) {
+                let next = match step(pos, dir) {
+                    Some(next) => next,
+                    None => continue,
+                };
+                let passage = self.passage(next, dir, target);
+                if passage == Passage::Blocked {
+                    continue;
+                }
+                let mut cost = STEP_COST;
+                if dirs != opposite(dir) {
+                    cost += CORNER_COST;
+                }
+                if passage == Passage::Crossing {
+                    cost += CROSSING_COST;
+                }
+                if cost < *costs.get(&(next, dir)).unwrap_or(&usize::MAX) {
+                    costs.insert((next, dir), cost);
+                    previous.insert((next, dir), (pos, 0));
+                    queue.push(Reverse((cost + heuristic(next), cost, next, dir)));
+                }
+            }
+        }
+
+        while let Some(Reverse((_, cost, pos, dir))) = queue.pop() {
+            if cost > costs[&(pos, dir)] {
+                continue;
+            }
+
+            if pos == target {
+                // Turning right is only possible on free blocks.
+                if dir == LEFT || (dir != RIGHT && self.canvas.get(pos.0, pos.1) != BlockType::Air)
+                {
+                    continue;
+                }
+                self.add_path(pos, dir, &previous);
+                return true;
+            }
+
+            let crossing = self.canvas.get(pos.0, pos.1) != BlockType::Air;
+            for &next_dir in DIRECTIONS.iter() {
+                if next_dir == opposite(dir) || (crossing && next_dir != dir) {
+                    continue;
+                }
+                let next = match step(pos, next_dir) {
+                    Some(next) => next,
+                    None => continue,
+                };
+                let passage = self.passage(next, next_dir, target);
+                if passage == Passage::Blocked {
+                    continue;
+                }
+
+                let mut next_cost = cost + STEP_COST;
+                if next_dir != dir {
+                    next_cost += CORNER_COST;
+                }
+                if passage == Passage::Crossing {
+                    next_cost += CROSSING_COST;
+                }
+                if next_cost < *costs.get(&(next, next_dir)).unwrap_or(&usize::MAX) {
+                    costs.insert((next, next_dir), next_cost);
+                    previous.insert((next, next_dir), (pos, dir));
+                    queue.push(Reverse((
+                        next_cost + heuristic(next),
+                        next_cost,
+                        next,
+                        next_dir,
+                    )));
+                }
+            }
+        }
+        false
+    }
+
+    // Adds the path ending at `target` (which leaves it to the right) to the tree.
+    fn add_path(&mut self, target: (usize, usize), dir: u8, previous: &HashMap<Node, Node>) {
+        let (mut pos, mut dir) = (target, dir);
+        let mut out = RIGHT;
+        loop {
+            if self.canvas.get(pos.0, pos.1) == BlockType::Air {
+                self.tree.insert(pos, opposite(dir) | out);
+            } else {
+                self.crossings.insert(pos);
+            }
+
+            let (prev, prev_dir) = previous[&(pos, dir)];
+            if prev_dir == 0 {
+                // Branching off the tree.
+                *self.tree.get_mut(&prev).unwrap() |= dir;
+                return;
+            }
+            out = dir;
+            pos = prev;
+            dir = prev_dir;
+        }
+    }
+}
+
+// Routes the given nets, returns the nets that could not be connected. These
+// are not drawn at all.
+pub fn route_nets(canvas: &mut Canvas, mut nets: Vec<MazeNet>) -> Vec<usize> {
+    let reserved = nets
+        .iter()
+        .flat_map(|n| n.sinks.iter().chain(std::iter::once(&n.source)))
+        .copied()
+        .collect::<HashSet<_>>();
+    nets.sort_by_key(|n| n.length());
+
+    let mut failed = Vec::new();
+    for maze_net in nets {
+        let (width, height) = canvas.dimensions();
+        let mut router = MazeRouter {
+            canvas: &*canvas,
+            reserved: &reserved,
+            tree: HashMap::new(),
+            crossings: HashSet::new(),
+            width: width + 1,
+            height: height + MARGIN,
+        };
+
+        // The source is either free or the start of a channel track.
+        let source = maze_net.source;
+        router.tree.insert(
+            source,
+            if canvas.get(source.0, source.1) == BlockType::WireH {
+                LEFT | RIGHT
+            } else {
+                LEFT
+            },
+        );
+
+        if !maze_net.sinks.iter().all(|&sink| router.route(sink)) {
+            failed.push(maze_net.net);
+            continue;
+        }
+
+        let MazeRouter {
+            tree, crossings, ..
+        } = router;
+        for (pos, dirs) in tree {
+            canvas.set(pos.0, pos.1, wire_block(dirs));
+        }
+        for pos in crossings {
+            canvas.set(pos.0, pos.1, BlockType::WireCrossing);
+        }
+    }
+    failed
+}
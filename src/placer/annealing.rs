@@ -0,0 +1,464 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Simulated annealing placer.
+//
+// Starts from an existing placement and moves gates around within their
+// column. Every column is a stack of gates, each with a gap of free rows above
+// it. Possible moves are swapping two gates, moving a single gate up or down,
+// growing or shrinking a gap and swapping the inputs of a gate.
+// The cost of a placement is estimated from the `ChannelLayout`s on both sides
+// of every channel, see `channel_cost`.
+
+use super::*;
+use crate::circuit::IOType;
+use crate::gate::BasicCircuitDetails;
+use std::cmp;
+
+// The density is a lower bound for the number of steps of a channel, every
+// net that does not go straight needs at least one wire.
+const DENSITY_WEIGHT: f64 = 1.0;
+const WIRE_WEIGHT: f64 = 0.3;
+const CROSSING_WEIGHT: f64 = 0.05;
+const WIRE_LENGTH_WEIGHT: f64 = 0.01;
+// Taller columns make the whole canvas taller.
+const HEIGHT_WEIGHT: f64 = 0.2;
+
+const MOVES_PER_GATE: usize = 4;
+// The initial placement is already quite good, so don't start with a random
+// one.
+const INITIAL_TEMPERATURE: f64 = 0.02;
+const COOLING_FACTOR: f64 = 0.85;
+// Stop when the temperature drops below this fraction of the initial one.
+const FINAL_TEMPERATURE: f64 = 0.005;
+
+// xorshift64*, good enough for moving gates around.
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Self {
+        // splitmix64, so that similar seeds give different sequences.
+        let mut z = seed.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        Rng(cmp::max(z ^ (z >> 31), 1))
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn unit(&mut self) -> f64 {
+        (self.next() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+// Estimates how expensive it is to route from `start` to `end`: the channel
+// density (the maximum number of nets that have to pass a row), the number of
+// wires, the number of pairs of nets that cross each other and the total wire
+// length.
+fn channel_cost(start: &ChannelLayout, end: &ChannelLayout) -> f64 {
+    // (net, whether it is a target, row) of every pin, grouped by net with the
+    // sources first.
+    let side = |layout: &ChannelLayout, target: bool| {
+        layout
+            .iter()
+            .enumerate()
+            .filter_map(move |(row, state)| match state {
+                ChannelState::Net(net) => Some((*net, target, row)),
+                _ => None,
+            })
+            .collect::<Vec<_>>()
+    };
+    let mut pins = side(start, false);
+    pins.extend(side(end, true));
+    pins.sort_unstable();
+
+    // (source, mean target, first row, last row) of every net on both sides.
+    let mut spans = Vec::new();
+    let mut first = 0;
+    while first < pins.len() {
+        let net = pins[first].0;
+        let last = first + pins[first..].iter().take_while(|p| p.0 == net).count();
+        let split = first + pins[first..last].iter().take_while(|p| !p.1).count();
+        let (from, to) = (&pins[first..split], &pins[split..last]);
+        first = last;
+        if from.is_empty() || to.is_empty() {
+            continue;
+        }
+
+        let min = to[0].2;
+        let max = to[to.len() - 1].2;
+        let from = from
+            .iter()
+            .map(|p| p.2)
+            .min_by_key(|&idx| cmp::max(max, idx) - cmp::min(min, idx))
+            .unwrap();
+        let target = to.iter().map(|p| p.2).sum::<usize>() as f64 / to.len() as f64;
+        spans.push((from, target, cmp::min(from, min), cmp::max(from, max)));
+    }
+
+    let rows = cmp::max(start.len(), end.len());
+    // As in `LayerRanges`, the row below a wire is kept free as well.
+    let mut passing = vec![0isize; rows + 2];
+    let mut wires = 0;
+    let mut wire_length = 0;
+    for &(_, _, first, last) in spans.iter().filter(|s| s.2 != s.3) {
+        passing[first] += 1;
+        passing[last + 2] -= 1;
+        wires += 1;
+        wire_length += last - first;
+    }
+    let mut density = 0;
+    let mut current = 0;
+    for p in passing {
+        current += p;
+        density = cmp::max(density, current);
+    }
+
+    let mut crossings = 0;
+    for (idx, a) in spans.iter().enumerate() {
+        for b in &spans[idx + 1..] {
+            if (a.0 as f64 - b.0 as f64) * (a.1 - b.1) < 0.0 {
+                crossings += 1;
+            }
+        }
+    }
+
+    DENSITY_WEIGHT * density as f64
+        + WIRE_WEIGHT * wires as f64
+        + CROSSING_WEIGHT * crossings as f64
+        + WIRE_LENGTH_WEIGHT * wire_length as f64
+}
+
+#[derive(Clone, Copy)]
+enum Move {
+    // Swaps the gates at two positions of the stack.
+    Swap(usize, usize),
+    // Moves the gate at this position of the stack down (or up), the gates
+    // below stay where they are.
+    Shift(usize, bool),
+    // Grows (or shrinks) the gap above the gate at this position, moving all
+    // gates below.
+    Gap(usize, bool),
+    // Swaps the inputs of this gate.
+    SwapInputs(usize),
+}
+
+#[derive(Clone)]
+struct Column {
+    // Indices of the gates from top to bottom.
+    order: Vec<usize>,
+    // Free rows above each position of the stack.
+    gaps: Vec<usize>,
+    // Whether the inputs of each gate are swapped.
+    swapped: Vec<bool>,
+}
+
+struct Annealer<'a> {
+    gate_hierarchy: &'a mut [Vec<Circuit>],
+    // Column 0 (the inputs) and the last column (the outputs) are never moved.
+    columns: Vec<Column>,
+    // Layouts on the left and right side of every column.
+    inputs: Vec<Vec<ChannelState>>,
+    outputs: Vec<Vec<ChannelState>>,
+    // Cost of the channel to the right of each column.
+    channel_costs: Vec<f64>,
+    heights: Vec<usize>,
+}
+
+impl<'a> Annealer<'a> {
+    fn new(gate_hierarchy: &'a mut [Vec<Circuit>]) -> Self {
+        let columns = gate_hierarchy
+            .iter()
+            .map(|circuits| {
+                let mut order = (0..circuits.len()).collect::<Vec<_>>();
+                order.sort_by_key(|&idx| circuits[idx].position.unwrap().1);
+                // Overlapping gates are pushed down.
+                let mut gaps = Vec::new();
+                let mut y = 0;
+                for &idx in &order {
+                    let top = circuits[idx].position.unwrap().1 as usize;
+                    gaps.push(top.saturating_sub(y));
+                    y = cmp::max(y, top) + circuits[idx].height() as usize;
+                }
+                Column {
+                    order,
+                    gaps,
+                    swapped: vec![false; circuits.len()],
+                }
+            })
+            .collect();
+
+        let mut annealer = Self {
+            gate_hierarchy,
+            columns,
+            inputs: Vec::new(),
+            outputs: Vec::new(),
+            channel_costs: Vec::new(),
+            heights: Vec::new(),
+        };
+        let n = annealer.columns.len();
+        annealer.inputs = (0..n)
+            .map(|col| annealer.layout(col, IOType::Input))
+            .collect();
+        annealer.outputs = (0..n)
+            .map(|col| annealer.layout(col, IOType::Output))
+            .collect();
+        annealer.heights = (0..annealer.columns.len())
+            .map(|col| annealer.height(col))
+            .collect();
+        annealer.channel_costs = (0..annealer.columns.len() - 1)
+            .map(|col| annealer.channel_cost(col))
+            .collect();
+        annealer
+    }
+
+    // Top row of every gate of the column, by gate index.
+    fn rows(&self, col: usize) -> Vec<usize> {
+        let column = &self.columns[col];
+        let circuits = &self.gate_hierarchy[col];
+        let mut rows = vec![0; circuits.len()];
+        let mut y = 0;
+        for (&idx, &gap) in column.order.iter().zip(&column.gaps) {
+            rows[idx] = y + gap;
+            y += gap + circuits[idx].height() as usize;
+        }
+        rows
+    }
+
+    fn height(&self, col: usize) -> usize {
+        let circuits = &self.gate_hierarchy[col];
+        self.columns[col]
+            .order
+            .iter()
+            .zip(&self.columns[col].gaps)
+            .map(|(&idx, &gap)| gap + circuits[idx].height() as usize)
+            .sum()
+    }
+
+    fn layout(&self, col: usize, io: IOType) -> Vec<ChannelState> {
+        let rows = self.rows(col);
+        let mut layout = Vec::new();
+        for (idx, circuit) in self.gate_hierarchy[col].iter().enumerate() {
+            let (ports, offset): (_, fn(&Circuit, usize) -> usize) = match io {
+                IOType::Input => (&circuit.inputs, |c, i| c.basic_circuit.input_y_offset(i)),
+                IOType::Output => (&circuit.outputs, |c, i| c.basic_circuit.output_y_offset(i)),
+            };
+            for (i, port) in ports.iter().enumerate() {
+                let row = if io == IOType::Input && self.columns[col].swapped[idx] {
+                    rows[idx] + offset(circuit, 1 - i)
+                } else {
+                    rows[idx] + offset(circuit, i)
+                };
+                while row >= layout.len() {
+                    layout.push(ChannelState::Occupied);
+                }
+                layout[row] = port.connection.into();
+            }
+        }
+        layout
+    }
+
+    fn channel_cost(&self, col: usize) -> f64 {
+        channel_cost(&self.outputs[col], &self.inputs[col + 1])
+    }
+
+    // Cost of everything a move in this column changes.
+    fn local_cost(&self, col: usize) -> f64 {
+        let mut cost = HEIGHT_WEIGHT * self.heights[col] as f64 + self.channel_costs[col - 1];
+        if col < self.channel_costs.len() {
+            cost += self.channel_costs[col];
+        }
+        cost
+    }
+
+    fn update_costs(&mut self, col: usize) {
+        self.inputs[col] = self.layout(col, IOType::Input);
+        self.outputs[col] = self.layout(col, IOType::Output);
+        self.heights[col] = self.height(col);
+        self.channel_costs[col - 1] = self.channel_cost(col - 1);
+        if col < self.channel_costs.len() {
+            self.channel_costs[col] = self.channel_cost(col);
+        }
+    }
+
+    fn random_move(&self, col: usize, rng: &mut Rng) -> Option<Move> {
+        let column = &self.columns[col];
+        let n = column.order.len();
+        let pos = rng.below(n);
+        match rng.below(4) {
+            0 if n > 1 => Some(Move::Swap(pos, rng.below(n))),
+            1 => {
+                let down = rng.below(2) == 0;
+                // Moving up needs a gap above, moving down needs one below.
+                let possible = if down {
+                    pos + 1 < n && column.gaps[pos + 1] > 0
+                } else {
+                    column.gaps[pos] > 0
+                };
+                if possible {
+                    Some(Move::Shift(pos, down))
+                } else {
+                    None
+                }
+            }
+            2 => {
+                let grow = rng.below(2) == 0;
+                if grow || column.gaps[pos] > 0 {
+                    Some(Move::Gap(pos, grow))
+                } else {
+                    None
+                }
+            }
+            3 if self.gate_hierarchy[col][column.order[pos]].can_swap_inputs()
+                && self.gate_hierarchy[col][column.order[pos]].inputs.len() == 2 =>
+            {
+                Some(Move::SwapInputs(column.order[pos]))
+            }
+            _ => None,
+        }
+    }
+
+    fn apply(&mut self, col: usize, m: Move) {
+        let column = &mut self.columns[col];
+        match m {
+            Move::Swap(a, b) => column.order.swap(a, b),
+            Move::Shift(pos, true) => {
+                column.gaps[pos] += 1;
+                if pos + 1 < column.gaps.len() {
+                    column.gaps[pos + 1] -= 1;
+                }
+            }
+            Move::Shift(pos, false) => {
+                column.gaps[pos] -= 1;
+                if pos + 1 < column.gaps.len() {
+                    column.gaps[pos + 1] += 1;
+                }
+            }
+            Move::Gap(pos, true) => column.gaps[pos] += 1,
+            Move::Gap(pos, false) => column.gaps[pos] -= 1,
+            Move::SwapInputs(idx) => column.swapped[idx] = !column.swapped[idx],
+        }
+    }
+
+    fn undo(&mut self, col: usize, m: Move) {
+        let inverse = match m {
+            Move::Shift(pos, down) => Move::Shift(pos, !down),
+            Move::Gap(pos, grow) => Move::Gap(pos, !grow),
+            m => m,
+        };
+        self.apply(col, inverse);
+    }
+
+    // Applies a move and returns the change of the cost.
+    fn try_move(&mut self, col: usize, m: Move) -> f64 {
+        let before = self.local_cost(col);
+        self.apply(col, m);
+        self.update_costs(col);
+        self.local_cost(col) - before
+    }
+
+    fn revert(&mut self, col: usize, m: Move) {
+        self.undo(col, m);
+        self.update_costs(col);
+    }
+
+    fn total_cost(&self) -> f64 {
+        self.channel_costs.iter().sum::<f64>()
+            + HEIGHT_WEIGHT * self.heights.iter().sum::<usize>() as f64
+    }
+
+    fn anneal(&mut self, seed: u64) {
+        let mut rng = Rng::new(seed);
+        let movable = (1..self.columns.len() - 1)
+            .filter(|&col| !self.columns[col].order.is_empty())
+            .collect::<Vec<_>>();
+        if movable.is_empty() {
+            return;
+        }
+        let gates = movable
+            .iter()
+            .map(|&col| self.columns[col].order.len())
+            .sum::<usize>();
+
+        let next_move = |annealer: &Self, rng: &mut Rng| loop {
+            let col = movable[rng.below(movable.len())];
+            if let Some(m) = annealer.random_move(col, rng) {
+                return (col, m);
+            }
+        };
+
+        let mut deltas = 0.0;
+        for _ in 0..100 {
+            let (col, m) = next_move(self, &mut rng);
+            deltas += self.try_move(col, m).abs();
+            self.revert(col, m);
+        }
+        let initial_temperature = INITIAL_TEMPERATURE * deltas / 100.0 + f64::EPSILON;
+        let mut temperature = initial_temperature;
+
+        // Keep the best placement seen at the end of a round.
+        let initial_cost = self.total_cost();
+        let mut best = (initial_cost, self.columns.clone());
+        while temperature > initial_temperature * FINAL_TEMPERATURE {
+            for _ in 0..MOVES_PER_GATE * gates {
+                let (col, m) = next_move(self, &mut rng);
+                let delta = self.try_move(col, m);
+                if delta > 0.0 && rng.unit() >= (-delta / temperature).exp() {
+                    self.revert(col, m);
+                }
+            }
+            if self.total_cost() < best.0 {
+                best = (self.total_cost(), self.columns.clone());
+            }
+            temperature *= COOLING_FACTOR;
+        }
+        self.columns = best.1;
+        println!(" [+] Estimated cost {:.1} -> {:.1}", initial_cost, best.0);
+    }
+
+    fn place(self) {
+        for col in 1..self.columns.len() - 1 {
+            let rows = self.rows(col);
+            let swapped = &self.columns[col].swapped;
+            for (idx, (circuit, row)) in self.gate_hierarchy[col].iter_mut().zip(rows).enumerate() {
+                if swapped[idx] {
+                    circuit.swap_inputs();
+                }
+                circuit.position = None;
+                circuit.place(Position2D(100_000, row as u32));
+            }
+        }
+    }
+}
+
+// Improves the placement of all gate groups (apart from the first and the last
+// one, the input and output pins) that have already been placed by
+// `place_gates`.
+pub fn place_gates_annealing(gate_hierarchy: &mut [Vec<Circuit>], seed: u64) {
+    if gate_hierarchy.len() < 3 {
+        return;
+    }
+    let mut annealer = Annealer::new(gate_hierarchy);
+    annealer.anneal(seed);
+    annealer.place();
+}
@@ -0,0 +1,247 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Barycenter placer, as used for drawing layered graphs (Sugiyama et al.).
+//
+// The columns are swept alternately from left to right and from right to left.
+// In every sweep, the gates of a column are sorted by the average row of the
+// pins they are connected to in the neighbouring column that was just placed,
+// and then stacked in this order as close as possible to that row. Every
+// inversion between two connections results in a wire crossing, so the
+// placement with the fewest inversions is kept.
+// Fewer crossings usually come at the cost of slightly wider channels.
+
+use super::*;
+use crate::circuit::{determine_channel_layout, IOType, PortConnection};
+use crate::gate::BasicCircuitDetails;
+use std::cmp;
+use std::collections::HashMap;
+
+const SWEEPS: usize = 8;
+// Free rows between two gates, the channel router needs some room to move
+// nets around.
+const SPACING: usize = 1;
+// How much more important it is to place gates with a single connection at
+// their desired row, as these can be connected by a straight wire.
+const ALIGN_WEIGHT: f64 = 100.0;
+
+// Counts the pairs of connections between two columns that cross each other.
+fn crossings(start: &ChannelLayout, end: &ChannelLayout) -> usize {
+    let mut sources = HashMap::new();
+    for (row, state) in start.iter().enumerate() {
+        if let ChannelState::Net(net) = state {
+            sources.entry(*net).or_insert(row);
+        }
+    }
+    let connections = end
+        .iter()
+        .enumerate()
+        .filter_map(|(row, state)| match state {
+            ChannelState::Net(net) => sources.get(net).map(|&source| (source, row)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+
+    let mut crossings = 0;
+    for (idx, a) in connections.iter().enumerate() {
+        for b in &connections[idx + 1..] {
+            if (a.0 < b.0 && a.1 > b.1) || (a.0 > b.0 && a.1 < b.1) {
+                crossings += 1;
+            }
+        }
+    }
+    crossings
+}
+
+fn total_crossings(gate_hierarchy: &[Vec<Circuit>]) -> usize {
+    (0..gate_hierarchy.len() - 1)
+        .map(|idx| {
+            crossings(
+                &determine_channel_layout(gate_hierarchy[idx].iter(), IOType::Output),
+                &determine_channel_layout(gate_hierarchy[idx + 1].iter(), IOType::Input),
+            )
+        })
+        .sum()
+}
+
+// Rows of all pins of the given column, by net.
+fn pin_rows(circuits: &[Circuit], io: IOType) -> HashMap<usize, Vec<usize>> {
+    let mut rows: HashMap<usize, Vec<usize>> = HashMap::new();
+    for circuit in circuits {
+        let ports = match io {
+            IOType::Input => &circuit.inputs,
+            IOType::Output => &circuit.outputs,
+        };
+        for port in ports {
+            if let PortConnection::Net(net) = port.connection {
+                rows.entry(net)
+                    .or_default()
+                    .push(port.position.unwrap().1 as usize);
+            }
+        }
+    }
+    rows
+}
+
+// Reorders the gates of a column using the pins of the neighbouring column
+// (the outputs on the left if `io` is `IOType::Input`, the inputs on the right
+// otherwise).
+fn sort_column(circuits: &mut [Circuit], neighbours: &HashMap<usize, Vec<usize>>, io: IOType) {
+    // Barycenter and desired top row of every gate: the one that aligns a pin
+    // with the neighbouring pin closest to the barycenter, so that at least
+    // one wire can go straight.
+    let placements = circuits
+        .iter()
+        .map(|circuit| {
+            let (ports, offset): (_, fn(&Circuit, usize) -> usize) = match io {
+                IOType::Input => (&circuit.inputs, |c, i| c.basic_circuit.input_y_offset(i)),
+                IOType::Output => (&circuit.outputs, |c, i| c.basic_circuit.output_y_offset(i)),
+            };
+            let mut tops = Vec::new();
+            for (i, port) in ports.iter().enumerate() {
+                if let Some(rows) = port
+                    .connection
+                    .get_net()
+                    .and_then(|net| neighbours.get(&net))
+                {
+                    tops.extend(
+                        rows.iter()
+                            .filter(|&&row| row >= offset(circuit, i))
+                            .map(|&row| row - offset(circuit, i)),
+                    );
+                }
+            }
+            if tops.is_empty() {
+                // Nothing to align with, stay where we are.
+                let top = circuit.position.unwrap().1 as usize;
+                return (top as f64, top, 1.0);
+            }
+            let barycenter = tops.iter().sum::<usize>() as f64 / tops.len() as f64;
+            let top = *tops
+                .iter()
+                .min_by(|&&a, &&b| {
+                    (a as f64 - barycenter)
+                        .abs()
+                        .partial_cmp(&(b as f64 - barycenter).abs())
+                        .unwrap()
+                        .then(a.cmp(&b))
+                })
+                .unwrap();
+            // A gate with a single connection can be aligned exactly.
+            let weight = if tops.len() == 1 { ALIGN_WEIGHT } else { 1.0 };
+            (barycenter, top, weight)
+        })
+        .collect::<Vec<_>>();
+    let barycenters = placements.iter().map(|p| p.0).collect::<Vec<_>>();
+
+    let mut order = (0..circuits.len()).collect::<Vec<_>>();
+    order.sort_by(|&a, &b| {
+        barycenters[a]
+            .partial_cmp(&barycenters[b])
+            .unwrap()
+            .then(circuits[a].position.cmp(&circuits[b].position))
+    });
+
+    // Gates must not overlap, so place them as close to their desired rows as
+    // possible (least squares) without changing their order. Subtracting the
+    // height of all gates above turns this into isotonic regression, solved
+    // by pooling adjacent violators.
+    let mut above = 0;
+    let mut blocks: Vec<(f64, f64, usize)> = Vec::new();
+    for &idx in &order {
+        let (_, desired, weight) = placements[idx];
+        let mut block = (weight * (desired as f64 - above as f64), weight, 1);
+        above += circuits[idx].height() as usize + SPACING;
+        while let Some(&(sum, w, n)) = blocks.last() {
+            if sum / w <= block.0 / block.1 {
+                break;
+            }
+            block = (block.0 + sum, block.1 + w, block.2 + n);
+            blocks.pop();
+        }
+        blocks.push(block);
+    }
+
+    let mut above = 0;
+    let mut order = order.into_iter();
+    for (sum, weight, n) in blocks {
+        let offset = cmp::max((sum / weight).round() as isize, 0) as usize;
+        for idx in order.by_ref().take(n) {
+            let circuit = &mut circuits[idx];
+            circuit.position = None;
+            circuit.place(Position2D(100_000, (above + offset) as u32));
+            above += circuit.height() as usize + SPACING;
+        }
+    }
+}
+
+// Swaps the inputs of all gates whose inputs come from the left in the
+// opposite order.
+fn swap_inputs(circuits: &mut [Circuit], sources: &HashMap<usize, Vec<usize>>) {
+    for circuit in circuits.iter_mut() {
+        if circuit.inputs.len() != 2 || !circuit.can_swap_inputs() {
+            continue;
+        }
+        let source = |idx: usize| {
+            circuit.inputs[idx]
+                .connection
+                .get_net()
+                .and_then(|net| sources.get(&net))
+                .map(|rows| rows[0])
+        };
+        if let (Some(p1), Some(p2)) = (source(0), source(1)) {
+            if p1 > p2 {
+                let position = circuit.position.take().unwrap();
+                circuit.swap_inputs();
+                circuit.place(Position2D(100_000, position.1));
+            }
+        }
+    }
+}
+
+// Reorders all gate groups (apart from the first and the last one, the input
+// and output pins) that have already been placed by `place_gates`.
+pub fn place_gates_barycenter(gate_hierarchy: &mut Vec<Vec<Circuit>>) {
+    if gate_hierarchy.len() < 3 {
+        return;
+    }
+
+    let initial_crossings = total_crossings(gate_hierarchy);
+    let mut best = (initial_crossings, gate_hierarchy.clone());
+    for sweep in 0..SWEEPS {
+        if sweep % 2 == 0 {
+            for idx in 1..gate_hierarchy.len() - 1 {
+                let sources = pin_rows(&gate_hierarchy[idx - 1], IOType::Output);
+                sort_column(&mut gate_hierarchy[idx], &sources, IOType::Input);
+                swap_inputs(&mut gate_hierarchy[idx], &sources);
+            }
+        } else {
+            for idx in (1..gate_hierarchy.len() - 1).rev() {
+                let targets = pin_rows(&gate_hierarchy[idx + 1], IOType::Input);
+                sort_column(&mut gate_hierarchy[idx], &targets, IOType::Output);
+            }
+        }
+
+        let crossings = total_crossings(gate_hierarchy);
+        if crossings < best.0 {
+            best = (crossings, gate_hierarchy.clone());
+        }
+    }
+
+    println!(
+        " [+] Estimated crossings {} -> {}",
+        initial_crossings, best.0
+    );
+    *gate_hierarchy = best.1;
+}
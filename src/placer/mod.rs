@@ -14,6 +14,25 @@
 
 use crate::channel_router::{ChannelLayout, ChannelState};
 use crate::circuit::{Circuit, Position2D};
+use crate::gate::BasicCircuitDetails;
+
+mod annealing;
+mod barycenter;
+pub use annealing::place_gates_annealing;
+pub use barycenter::place_gates_barycenter;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Placer {
+    // Aligns single-input gates with their source and stacks everything else,
+    // see `place_gates`.
+    Simple,
+    // Improves the simple placement by simulated annealing, using the given
+    // seed. See `annealing.rs`.
+    Annealing(u64),
+    // Reorders the gates of every column to reduce wire crossings, see
+    // `barycenter.rs`.
+    Barycenter,
+}
 
 fn get_net_index_in_layout(channel_layout: &ChannelLayout, net: usize) -> Option<usize> {
     channel_layout
@@ -39,31 +58,48 @@ pub fn place_gates(channel_layout: &ChannelLayout, circuits: &mut Vec<Circuit>)
                 continue;
             }
 
-            // Check where the inputs are that we need.
+            // Check where the inputs are that we need (nets that are not
+            // part of the channel are connected by the maze router).
             let p1 = get_net_index_in_layout(
                 channel_layout,
                 circuit.inputs[0].connection.get_net().unwrap(),
-            )
-            .unwrap();
+            );
             let p2 = get_net_index_in_layout(
                 channel_layout,
                 circuit.inputs[1].connection.get_net().unwrap(),
-            )
-            .unwrap();
+            );
 
-            if p1 > p2 {
-                circuit.swap_inputs();
+            if let (Some(p1), Some(p2)) = (p1, p2) {
+                if p1 > p2 {
+                    circuit.swap_inputs();
+                }
             }
         } else if circuit.inputs.len() == 1 {
             if let Some(req_input) = circuit.inputs[0].connection.get_net() {
-                let p = get_net_index_in_layout(channel_layout, req_input)
-                    .unwrap_or_else(|| panic!("Could not find net {}", req_input));
+                let p = match get_net_index_in_layout(channel_layout, req_input) {
+                    Some(p) => p,
+                    None => continue,
+                };
 
-                // If the target slot is still available, place ourselves here.
-                // Note: This will also only work with 1x1 gates, circuits might
-                //       overlap.
-                if desired_channel_layout[p].is_free() {
-                    circuit.place(Position2D(100_000, p as u32));
+                // If the rows next to the target slot are still available,
+                // place ourselves there with the input at the target slot.
+                let offset = circuit.basic_circuit.input_y_offset(0);
+                if p < offset {
+                    continue;
+                }
+                let top = p - offset;
+                let rows = top..top + circuit.height() as usize;
+                while rows.end > desired_channel_layout.len() {
+                    desired_channel_layout.push(ChannelState::Free);
+                }
+                if desired_channel_layout[rows.clone()]
+                    .iter()
+                    .all(|s| s.is_free())
+                {
+                    circuit.place(Position2D(100_000, top as u32));
+                    for off in rows {
+                        desired_channel_layout[off] = ChannelState::Occupied;
+                    }
                     desired_channel_layout[p] = ChannelState::Net(req_input);
                 }
             }
@@ -103,12 +139,16 @@ pub fn place_gates(channel_layout: &ChannelLayout, circuits: &mut Vec<Circuit>)
             desired_channel_layout[off] = i.connection.into();
         }
 
-        // Hack: Make sure to mark the space between the inputs as occupied.
-        assert!(circuit.inputs.len() == 1 || circuit.inputs.len() == 2);
-        if circuit.inputs.len() == 2 {
-            let off = circuit.inputs[0].position.unwrap().1 as usize;
-            assert!(desired_channel_layout[off + 1].is_free());
-            desired_channel_layout[off + 1] = ChannelState::Occupied;
+        // Hack: Make sure to mark the space between the inputs (and below
+        // them, for gates like flip-flops) as occupied.
+        let top = circuit.position.unwrap().1 as usize;
+        for off in top..top + circuit.height() as usize {
+            while off >= desired_channel_layout.len() {
+                desired_channel_layout.push(ChannelState::Free);
+            }
+            if desired_channel_layout[off].is_free() {
+                desired_channel_layout[off] = ChannelState::Occupied;
+            }
         }
     }
 
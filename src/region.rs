@@ -0,0 +1,170 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Submodules can be kept as placement regions: every module is placed and
+// routed on its own canvas, which is then used like a hard macro by the
+// modules instantiating it. This keeps each instance in one rectangle and
+// all instances of a module share the same layout.
+//
+// The input pins of the module are connected to the left edge of the region,
+// its output pins to the right edge.
+
+use crate::canvas::{BlockType, Canvas};
+use crate::circuit::{Circuit, Port};
+use crate::gate::{BasicCircuitDetails, HardMacro};
+use crate::loader::{bit_name, YosysJsonModule, YosysJsonPortDirection};
+
+// Extends the pin at (x, y) to the given columns of the canvas.
+fn extend_pin(
+    canvas: &mut Canvas,
+    name: &str,
+    y: usize,
+    columns: std::ops::Range<usize>,
+) -> Result<(), String> {
+    for x in columns {
+        match canvas.get(x, y) {
+            BlockType::Air | BlockType::WireH | BlockType::WireV => {
+                canvas.set_wire(x, y, BlockType::WireH)
+            }
+            _ => {
+                return Err(format!(
+                    "Can't connect region {} to the edge of its canvas",
+                    name
+                ))
+            }
+        }
+    }
+    Ok(())
+}
+
+// Pins of the given direction as (name, net), in a stable order.
+fn pins(module: &YosysJsonModule, direction: YosysJsonPortDirection) -> Vec<(String, Port)> {
+    let mut ports = module
+        .ports
+        .iter()
+        .filter(|(_, p)| p.direction == direction)
+        .collect::<Vec<_>>();
+    ports.sort_by_key(|(name, _)| name.as_str());
+    ports
+        .into_iter()
+        .flat_map(|(name, p)| {
+            p.bits
+                .iter()
+                .enumerate()
+                .map(move |(idx, bit)| (bit_name(name, p.bits.len(), idx), Port::from(bit)))
+        })
+        .collect()
+}
+
+// Returns the module without the input bits that aren't connected to
+// anything, e.g. unused bits of a bus. They can't be placed, but still get a
+// pin in `region_macro`.
+pub fn without_unused_inputs(module: &YosysJsonModule) -> YosysJsonModule {
+    let used = module
+        .cells
+        .values()
+        .flat_map(|c| c.connections.values().flatten())
+        .chain(
+            module
+                .ports
+                .values()
+                .filter(|p| p.direction == YosysJsonPortDirection::Output)
+                .flat_map(|p| p.bits.iter()),
+        )
+        .cloned()
+        .collect::<Vec<_>>();
+    let mut module = module.clone();
+    for port in module.ports.values_mut() {
+        if port.direction == YosysJsonPortDirection::Input {
+            port.bits.retain(|bit| !bit.is_u64() || used.contains(bit));
+        }
+    }
+    module.ports.retain(|_, p| !p.bits.is_empty());
+    module
+}
+
+// Turns the placed and routed module into a hard macro.
+pub fn region_macro(
+    name: &str,
+    module: &YosysJsonModule,
+    mut canvas: Canvas,
+    gate_hierarchy: &[Vec<Circuit>],
+) -> Result<HardMacro, String> {
+    let (width, height) = canvas.dimensions();
+
+    // Unused inputs get a row below the placed module, with a single wire
+    // that isn't connected to anything.
+    let mut next_unused_row = height;
+    let mut inputs = Vec::new();
+    for (pin, port) in pins(module, YosysJsonPortDirection::Input) {
+        let input = gate_hierarchy[0]
+            .iter()
+            .find(|c| c.basic_circuit.is_input() && c.outputs[0].connection == port.connection);
+        let y = match input {
+            Some(input) => {
+                let position = input
+                    .position
+                    .ok_or_else(|| format!("Region {}: Input {} was not placed", name, pin))?;
+                let (x, y) = (position.0 as usize, position.1 as usize);
+                canvas.set(x, y, BlockType::WireH);
+                extend_pin(&mut canvas, name, y, 0..x)?;
+                y
+            }
+            None => {
+                next_unused_row += 1;
+                canvas.set(0, next_unused_row - 1, BlockType::WireH);
+                next_unused_row - 1
+            }
+        };
+        inputs.push((pin, y));
+    }
+
+    // Several output pins can be connected to the same net.
+    let mut output_circuits = gate_hierarchy
+        .last()
+        .unwrap()
+        .iter()
+        .filter(|c| c.basic_circuit.is_output())
+        .collect::<Vec<_>>();
+    let mut outputs = Vec::new();
+    for (pin, port) in pins(module, YosysJsonPortDirection::Output) {
+        let idx = output_circuits
+            .iter()
+            .position(|c| c.inputs[0].connection == port.connection)
+            .ok_or_else(|| format!("Region {}: Output {} was not placed", name, pin))?;
+        let position = output_circuits
+            .remove(idx)
+            .position
+            .ok_or_else(|| format!("Region {}: Output {} was not placed", name, pin))?;
+        let (x, y) = (position.0 as usize, position.1 as usize);
+        // Outputs tied to a constant keep their constant block.
+        if canvas.get(x, y) != BlockType::Constant {
+            canvas.set(x, y, BlockType::WireH);
+        }
+        extend_pin(&mut canvas, name, y, x + 1..width)?;
+        outputs.push((pin, y));
+    }
+
+    let delay = gate_hierarchy
+        .iter()
+        .map(|g| g.iter().map(|c| c.basic_circuit.delay()).max().unwrap_or(0))
+        .sum();
+    let sequential = gate_hierarchy
+        .iter()
+        .flatten()
+        .any(|c| c.basic_circuit.is_sequential());
+    Ok(HardMacro::from_canvas(
+        name, &canvas, &inputs, &outputs, delay, sequential,
+    ))
+}
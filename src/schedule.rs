@@ -0,0 +1,246 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Moves gates between the levels of the gate hierarchy.
+//
+// `resolve_gate_dependencies` schedules every gate as soon as possible. A net
+// that is consumed n levels after it was produced needs n - 1 'forwarding'
+// gates, so moving gates closer to their consumers (or producers) saves
+// these. Input, output and feedback pins as well as sequential gates are never
+// moved.
+
+use crate::circuit::{Circuit, PortConnection};
+use std::cmp;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+
+// Maximum number of passes over all gates for `Schedule::Balanced`.
+const MAX_PASSES: usize = 16;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Schedule {
+    // As soon as possible, as done by `resolve_gate_dependencies`.
+    Asap,
+    // As late as possible.
+    Alap,
+    // Starts with ASAP (and ALAP) and moves every gate within its mobility
+    // (the levels between ASAP and ALAP) to where it needs the fewest
+    // forwarding gates.
+    Balanced,
+}
+
+impl TryFrom<&str> for Schedule {
+    type Error = ();
+
+    fn try_from(name: &str) -> Result<Self, Self::Error> {
+        match name {
+            "asap" => Ok(Schedule::Asap),
+            "alap" => Ok(Schedule::Alap),
+            "balanced" => Ok(Schedule::Balanced),
+            _ => Err(()),
+        }
+    }
+}
+
+struct Graph {
+    // Gate indices that produce each net.
+    producers: HashMap<usize, usize>,
+    // Gate indices that consume each net.
+    consumers: HashMap<usize, Vec<usize>>,
+}
+
+impl Graph {
+    fn new(gates: &[Circuit]) -> Self {
+        let mut producers = HashMap::new();
+        let mut consumers: HashMap<usize, Vec<usize>> = HashMap::new();
+        for (idx, gate) in gates.iter().enumerate() {
+            for o in &gate.outputs {
+                // Sequential gates in the last level produce the same nets as
+                // their feedback pins in the first one, which come first.
+                if let PortConnection::Net(net) = o.connection {
+                    producers.entry(net).or_insert(idx);
+                }
+            }
+            for i in &gate.inputs {
+                if let PortConnection::Net(net) = i.connection {
+                    consumers.entry(net).or_default().push(idx);
+                }
+            }
+        }
+        Self {
+            producers,
+            consumers,
+        }
+    }
+
+    fn predecessors<'a>(&'a self, gate: &'a Circuit) -> impl Iterator<Item = usize> + 'a {
+        gate.inputs
+            .iter()
+            .filter_map(move |i| i.connection.get_net())
+            .filter_map(move |net| self.producers.get(&net).copied())
+    }
+
+    fn successors<'a>(&'a self, gate: &'a Circuit) -> impl Iterator<Item = usize> + 'a {
+        gate.outputs
+            .iter()
+            .filter_map(move |o| o.connection.get_net())
+            .flat_map(move |net| self.consumers.get(&net).into_iter().flatten().copied())
+    }
+
+    // Number of forwarding gates required for a net.
+    fn forwarding_gates(&self, net: usize, levels: &[usize]) -> usize {
+        let produced = levels[self.producers[&net]];
+        let consumed = self.consumers[&net]
+            .iter()
+            .map(|&c| levels[c])
+            .max()
+            .unwrap();
+        consumed - produced - 1
+    }
+
+    fn total_forwarding_gates(&self, levels: &[usize]) -> usize {
+        self.producers
+            .keys()
+            .filter(|net| self.consumers.contains_key(net))
+            .map(|&net| self.forwarding_gates(net, levels))
+            .sum()
+    }
+
+    // Number of forwarding gates required for all nets of this gate.
+    fn local_forwarding_gates(&self, gate: &Circuit, levels: &[usize]) -> usize {
+        gate.inputs
+            .iter()
+            .chain(gate.outputs.iter())
+            .filter_map(|p| p.connection.get_net())
+            .filter(|net| self.producers.contains_key(net) && self.consumers.contains_key(net))
+            .map(|net| self.forwarding_gates(net, levels))
+            .sum()
+    }
+
+    // Moves the given gates one by one to the level within their predecessors
+    // and successors where they need the fewest forwarding gates.
+    fn balance(
+        &self,
+        gates: &[Circuit],
+        order: &[usize],
+        mut levels: Vec<usize>,
+        n_levels: usize,
+    ) -> Vec<usize> {
+        for _ in 0..MAX_PASSES {
+            let mut changed = false;
+            for &idx in order {
+                let gate = &gates[idx];
+                let first = self
+                    .predecessors(gate)
+                    .map(|p| levels[p] + 1)
+                    .max()
+                    .unwrap_or(0);
+                let last = self
+                    .successors(gate)
+                    .map(|s| levels[s] - 1)
+                    .min()
+                    .unwrap_or(n_levels - 2);
+
+                let current = levels[idx];
+                let mut best = (self.local_forwarding_gates(gate, &levels), current);
+                for level in first..=last {
+                    levels[idx] = level;
+                    let cost = self.local_forwarding_gates(gate, &levels);
+                    if cost < best.0 {
+                        best = (cost, level);
+                    }
+                }
+                levels[idx] = best.1;
+                changed |= best.1 != current;
+            }
+            if !changed {
+                break;
+            }
+        }
+        levels
+    }
+}
+
+// Rebuilds the gate hierarchy from the level of every gate.
+fn build_hierarchy(gates: Vec<Circuit>, levels: &[usize], n_levels: usize) -> Vec<Vec<Circuit>> {
+    let mut gate_hierarchy = vec![Vec::new(); n_levels];
+    for (gate, &level) in gates.into_iter().zip(levels) {
+        gate_hierarchy[level].push(gate);
+    }
+    // Sort gates by output net number, as `resolve_gate_dependencies` does.
+    for level in gate_hierarchy.iter_mut().take(n_levels - 1) {
+        level.sort_by(|a, b| a.outputs[0].cmp(&b.outputs[0]));
+    }
+    gate_hierarchy
+}
+
+pub fn reschedule(gate_hierarchy: Vec<Vec<Circuit>>, schedule: Schedule) -> Vec<Vec<Circuit>> {
+    if let Schedule::Asap = schedule {
+        return gate_hierarchy;
+    }
+
+    let n_levels = gate_hierarchy.len();
+    let mut asap = Vec::new();
+    let mut fixed = Vec::new();
+    let mut gates = Vec::new();
+    for (level, group) in gate_hierarchy.into_iter().enumerate() {
+        for gate in group {
+            asap.push(level);
+            fixed.push(
+                gate.basic_circuit.is_input()
+                    || gate.basic_circuit.is_feedback()
+                    || level == n_levels - 1,
+            );
+            gates.push(gate);
+        }
+    }
+    let graph = Graph::new(&gates);
+
+    // Go through the gates from the last level to the first one, so that all
+    // successors are already scheduled.
+    let mut order = (0..gates.len()).collect::<Vec<_>>();
+    order.sort_by_key(|&idx| cmp::Reverse(asap[idx]));
+    let mut alap = asap.clone();
+    for &idx in &order {
+        if fixed[idx] {
+            continue;
+        }
+        alap[idx] = graph
+            .successors(&gates[idx])
+            .map(|s| alap[s] - 1)
+            .min()
+            .unwrap_or(n_levels - 2);
+    }
+
+    let levels = match schedule {
+        Schedule::Asap => unreachable!(),
+        Schedule::Alap => alap,
+        Schedule::Balanced => {
+            // Gates with the least mobility are the most constrained ones, so
+            // move them first.
+            let mut order = (0..gates.len())
+                .filter(|&idx| !fixed[idx] && alap[idx] > asap[idx])
+                .collect::<Vec<_>>();
+            order.sort_by_key(|&idx| (alap[idx] - asap[idx], asap[idx]));
+
+            let from_asap = graph.balance(&gates, &order, asap, n_levels);
+            let from_alap = graph.balance(&gates, &order, alap, n_levels);
+            cmp::min_by_key(from_asap, from_alap, |levels| {
+                graph.total_forwarding_gates(levels)
+            })
+        }
+    };
+
+    build_hierarchy(gates, &levels, n_levels)
+}
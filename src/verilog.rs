@@ -0,0 +1,855 @@
+// Copyright 2019 Google LLC
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     https://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+
+// Reads structural (gate-level) Verilog netlists, as written by yosys'
+// `write_verilog -noattr` after mapping the design to the available cells.
+// Every module becomes a module like the ones of a yosys JSON file.
+//
+// Only the subset used by netlists is supported: port, wire and reg
+// declarations, `assign` statements between nets and constants, and instances
+// of cells or other modules with named port connections, e.g.
+// `AND g1 (.A(a), .B(b), .Y(y));`. Definitions of modules that are cells
+// themselves (e.g. stubs of hard macros) are skipped.
+
+use crate::gate::{BasicCircuitDetails, BasicCircuitYada, CellAlias, CellLibrary};
+use crate::liberty::find;
+use crate::loader::{
+    YosysJson, YosysJsonCell, YosysJsonModule, YosysJsonPort, YosysJsonPortDirection,
+};
+use core::convert::TryFrom;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Read;
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    Number(String),
+    Symbol(char),
+}
+
+fn starts_with(chars: &[char], s: &str) -> bool {
+    chars.iter().copied().take(s.len()).eq(s.chars())
+}
+
+// Splits the file into tokens and the lines they are on. Comments, attributes
+// and compiler directives are dropped.
+fn tokenize(buf: &str) -> Vec<(Token, usize)> {
+    let chars = buf.chars().collect::<Vec<_>>();
+    let mut tokens = Vec::new();
+    let mut line = 1;
+    let mut idx = 0;
+    let take_while = |mut idx: usize, f: &dyn Fn(char) -> bool| {
+        while idx < chars.len() && f(chars[idx]) {
+            idx += 1;
+        }
+        idx
+    };
+    while idx < chars.len() {
+        let c = chars[idx];
+        if c == '\n' {
+            line += 1;
+            idx += 1;
+        } else if c.is_whitespace() {
+            idx += 1;
+        } else if starts_with(&chars[idx..], "//") || c == '`' {
+            idx = take_while(idx, &|c| c != '\n');
+        } else if let Some((_, end)) = [("/*", "*/"), ("(*", "*)")]
+            .iter()
+            .find(|(start, _)| starts_with(&chars[idx..], start))
+        {
+            idx += 2;
+            while idx < chars.len() && !starts_with(&chars[idx..], end) {
+                if chars[idx] == '\n' {
+                    line += 1;
+                }
+                idx += 1;
+            }
+            idx += 2;
+        } else if c == '\\' {
+            // Escaped identifiers end at the next whitespace.
+            let end = take_while(idx + 1, &|c| !c.is_whitespace());
+            tokens.push((Token::Ident(chars[idx + 1..end].iter().collect()), line));
+            idx = end;
+        } else if c.is_alphabetic() || c == '_' || c == '$' {
+            let end = take_while(idx, &|c| c.is_alphanumeric() || c == '_' || c == '$');
+            tokens.push((Token::Ident(chars[idx..end].iter().collect()), line));
+            idx = end;
+        } else if c.is_ascii_digit() || c == '\'' {
+            let end = take_while(idx, &|c| {
+                c.is_alphanumeric() || c == '_' || c == '\'' || c == '?'
+            });
+            tokens.push((Token::Number(chars[idx..end].iter().collect()), line));
+            idx = end;
+        } else {
+            tokens.push((Token::Symbol(c), line));
+            idx += 1;
+        }
+    }
+    tokens
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Bit {
+    Net(usize),
+    // "0", "1" or "x", like in yosys JSON files.
+    Constant(&'static str),
+}
+
+// A (vector) wire. The bits are numbered starting at the right index of the
+// declaration, e.g. the bits of `wire [7:0] a` are a[0] to a[7].
+struct Wire {
+    left: i64,
+    right: i64,
+    first_net: usize,
+}
+
+impl Wire {
+    fn width(&self) -> usize {
+        ((self.left - self.right).abs() + 1) as usize
+    }
+
+    fn net(&self, idx: i64) -> Option<usize> {
+        let offset = if self.left >= self.right {
+            idx - self.right
+        } else {
+            self.right - idx
+        };
+        if offset >= 0 && (offset as usize) < self.width() {
+            Some(self.first_net + offset as usize)
+        } else {
+            None
+        }
+    }
+
+    fn bits(&self) -> Vec<Bit> {
+        (0..self.width())
+            .map(|offset| Bit::Net(self.first_net + offset))
+            .collect()
+    }
+}
+
+struct Instance {
+    cell_type: String,
+    name: String,
+    connections: Vec<(String, Vec<Bit>)>,
+    line: usize,
+}
+
+#[derive(Default)]
+struct Module {
+    name: String,
+    ports: Vec<String>,
+    directions: HashMap<String, YosysJsonPortDirection>,
+    wires: HashMap<String, Wire>,
+    n_nets: usize,
+    // Left and right hand side of `assign` statements.
+    assignments: Vec<(Vec<Bit>, Vec<Bit>)>,
+    instances: Vec<Instance>,
+}
+
+impl Module {
+    // Declares a new wire, repeated declarations (e.g. `output [3:0] y;` and
+    // `wire [3:0] y;`) refer to the same wire.
+    fn declare(&mut self, name: &str, range: Option<(i64, i64)>) {
+        if !self.wires.contains_key(name) {
+            let (left, right) = range.unwrap_or((0, 0));
+            let wire = Wire {
+                left,
+                right,
+                first_net: self.n_nets,
+            };
+            self.n_nets += wire.width();
+            self.wires.insert(name.to_string(), wire);
+        }
+    }
+
+    fn into_module(
+        self,
+        filepath: &str,
+        library: &CellLibrary,
+        is_module: &dyn Fn(&str) -> bool,
+    ) -> YosysJsonModule {
+        let Module {
+            name,
+            ports,
+            directions,
+            wires,
+            n_nets,
+            assignments,
+            instances,
+        } = self;
+
+        // Nets that are assigned to each other become one net. Missing bits
+        // on the right hand side are zero.
+        let mut parent = (0..n_nets).collect::<Vec<_>>();
+        let pairs = assignments
+            .iter()
+            .flat_map(|(lhs, rhs)| {
+                lhs.iter()
+                    .zip(rhs.iter().chain(std::iter::repeat(&Bit::Constant("0"))))
+            })
+            .map(|(lhs, rhs)| match lhs {
+                Bit::Net(net) => (*net, *rhs),
+                Bit::Constant(_) => panic!("Module {}: Can't assign to a constant", name),
+            })
+            .collect::<Vec<_>>();
+        for &(lhs, rhs) in pairs.iter() {
+            if let Bit::Net(rhs) = rhs {
+                let (a, b) = (find(&mut parent, lhs), find(&mut parent, rhs));
+                parent[a] = b;
+            }
+        }
+        let mut constants = HashMap::new();
+        for &(lhs, rhs) in pairs.iter() {
+            if let Bit::Constant(value) = rhs {
+                constants.insert(find(&mut parent, lhs), value);
+            }
+        }
+        let mut value = |bit: &Bit| match *bit {
+            Bit::Constant(value) => Value::from(value),
+            Bit::Net(net) => {
+                let net = find(&mut parent, net);
+                match constants.get(&net) {
+                    Some(&value) => Value::from(value),
+                    None => Value::from(net as u64 + 2),
+                }
+            }
+        };
+
+        let mut cells = HashMap::new();
+        for instance in instances {
+            if !is_module(&instance.cell_type) {
+                let alias = CellAlias::get(&instance.cell_type);
+                let cell = library
+                    .get(&instance.cell_type)
+                    .or_else(|| alias.map(|a| a.cell))
+                    .or_else(|| BasicCircuitYada::try_from(&*instance.cell_type).ok())
+                    .unwrap_or_else(|| {
+                        panic!(
+                            "{}:{}: Unknown cell type {}",
+                            filepath, instance.line, instance.cell_type
+                        )
+                    });
+                for (port, _) in instance.connections.iter() {
+                    let port = alias.map(|a| a.port_name(port)).unwrap_or(port);
+                    if !cell
+                        .input_names()
+                        .iter()
+                        .chain(cell.output_names().iter())
+                        .any(|name| *name == port)
+                    {
+                        panic!(
+                            "{}:{}: Cell {} has no port {}",
+                            filepath, instance.line, instance.cell_type, port
+                        );
+                    }
+                }
+            }
+            let connections = instance
+                .connections
+                .iter()
+                .map(|(port, bits)| (port.clone(), bits.iter().map(&mut value).collect()))
+                .collect();
+            cells.insert(
+                instance.name,
+                YosysJsonCell::new(&instance.cell_type, connections),
+            );
+        }
+
+        let ports = ports
+            .into_iter()
+            .map(|port| {
+                let direction = directions
+                    .get(&port)
+                    .cloned()
+                    .unwrap_or_else(|| panic!("Module {}: Port {} has no direction", name, port));
+                let bits = wires[&port].bits().iter().map(&mut value).collect();
+                (port, YosysJsonPort { direction, bits })
+            })
+            .collect();
+        YosysJsonModule::new(ports, cells, false)
+    }
+}
+
+struct Parser<'a> {
+    filepath: &'a str,
+    tokens: Vec<(Token, usize)>,
+    pos: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn line(&self) -> usize {
+        self.tokens
+            .get(self.pos)
+            .or_else(|| self.tokens.last())
+            .map(|(_, line)| *line)
+            .unwrap_or(0)
+    }
+
+    fn error(&self, msg: &str) -> ! {
+        panic!("{}:{}: {}", self.filepath, self.line(), msg)
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos).map(|(token, _)| token)
+    }
+
+    fn next(&mut self) -> Token {
+        let token = self
+            .peek()
+            .cloned()
+            .unwrap_or_else(|| self.error("Unexpected end of file"));
+        self.pos += 1;
+        token
+    }
+
+    fn accept(&mut self, c: char) -> bool {
+        if self.peek() == Some(&Token::Symbol(c)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) {
+        if !self.accept(c) {
+            self.error(&format!("Expected '{}'", c));
+        }
+    }
+
+    fn accept_keyword(&mut self, keyword: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Ident(k)) if k == keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ident(&mut self) -> String {
+        match self.next() {
+            Token::Ident(name) => name,
+            t => {
+                self.pos -= 1;
+                self.error(&format!("Expected an identifier, found {:?}", t))
+            }
+        }
+    }
+
+    fn integer(&mut self) -> i64 {
+        match self.next() {
+            Token::Number(n) => n
+                .parse()
+                .unwrap_or_else(|_| self.error(&format!("Expected an integer, found {}", n))),
+            t => self.error(&format!("Expected an integer, found {:?}", t)),
+        }
+    }
+
+    // Skips a parenthesized list, e.g. the parameters of an instance.
+    fn skip_parens(&mut self) {
+        self.expect('(');
+        let mut depth = 1;
+        while depth > 0 {
+            match self.next() {
+                Token::Symbol('(') => depth += 1,
+                Token::Symbol(')') => depth -= 1,
+                _ => {}
+            }
+        }
+    }
+
+    fn range(&mut self) -> Option<(i64, i64)> {
+        if self.accept('[') {
+            let left = self.integer();
+            self.expect(':');
+            let right = self.integer();
+            self.expect(']');
+            Some((left, right))
+        } else {
+            None
+        }
+    }
+
+    fn direction(&mut self) -> Option<YosysJsonPortDirection> {
+        if self.accept_keyword("input") {
+            Some(YosysJsonPortDirection::Input)
+        } else if self.accept_keyword("output") {
+            Some(YosysJsonPortDirection::Output)
+        } else if self.accept_keyword("inout") {
+            self.error("Bidirectional ports are not supported")
+        } else {
+            None
+        }
+    }
+
+    fn net_type(&mut self) {
+        while self.accept_keyword("wire")
+            || self.accept_keyword("reg")
+            || self.accept_keyword("signed")
+        {}
+    }
+
+    // Sized (`4'b1x01`, `1'h0`) or unsized (decimal) constants, least
+    // significant bit first.
+    fn number(&self, literal: &str) -> Vec<Bit> {
+        let invalid = format!("Invalid number {}", literal);
+        let (width, value) = match literal.split_once('\'') {
+            Some(("", value)) => (32, value),
+            Some((width, value)) => (
+                width.parse().unwrap_or_else(|_| self.error(&invalid)),
+                value,
+            ),
+            None => (32, literal),
+        };
+        let value = value.trim_start_matches(['s', 'S']);
+        let (base, digits) = match value.chars().next() {
+            Some(c) if literal.contains('\'') => (c.to_ascii_lowercase(), &value[1..]),
+            _ => ('d', value),
+        };
+        let digits = digits.replace('_', "");
+        if digits.is_empty() {
+            self.error(&invalid);
+        }
+
+        let mut bits = Vec::new();
+        match base {
+            'b' | 'o' | 'h' => {
+                let (radix, n) = match base {
+                    'b' => (2, 1),
+                    'o' => (8, 3),
+                    _ => (16, 4),
+                };
+                for c in digits.chars().rev() {
+                    match c.to_digit(radix) {
+                        Some(digit) => bits.extend((0..n).map(|bit| {
+                            Bit::Constant(if digit >> bit & 1 == 1 { "1" } else { "0" })
+                        })),
+                        None if "xXzZ?".contains(c) => {
+                            bits.extend((0..n).map(|_| Bit::Constant("x")))
+                        }
+                        None => self.error(&invalid),
+                    }
+                }
+            }
+            'd' => {
+                let value: u64 = digits.parse().unwrap_or_else(|_| self.error(&invalid));
+                bits.extend(
+                    (0..64).map(|bit| Bit::Constant(if value >> bit & 1 == 1 { "1" } else { "0" })),
+                );
+            }
+            _ => self.error(&invalid),
+        }
+
+        // Like in Verilog, unknown values are extended with unknown values.
+        let fill = match bits.last() {
+            Some(Bit::Constant("x")) => "x",
+            _ => "0",
+        };
+        bits.resize(width, Bit::Constant(fill));
+        bits
+    }
+
+    // The bits of an expression, least significant bit first.
+    fn expression(&mut self, module: &mut Module) -> Vec<Bit> {
+        match self.next() {
+            Token::Symbol('{') => {
+                // Replication, e.g. `{4{a}}`.
+                if let (Some(Token::Number(_)), Some((Token::Symbol('{'), _))) =
+                    (self.peek(), self.tokens.get(self.pos + 1))
+                {
+                    let n = self.integer();
+                    let bits = self.expression(module);
+                    self.expect('}');
+                    return bits.repeat(n as usize);
+                }
+                let mut parts = vec![self.expression(module)];
+                while self.accept(',') {
+                    parts.push(self.expression(module));
+                }
+                self.expect('}');
+                // The first part holds the most significant bits.
+                parts.into_iter().rev().flatten().collect()
+            }
+            Token::Number(literal) => self.number(&literal),
+            Token::Ident(name) => {
+                // Undeclared nets are implicitly declared as single wires.
+                module.declare(&name, None);
+                let wire = &module.wires[&name];
+                let indices = if self.accept('[') {
+                    let left = self.integer();
+                    let right = if self.accept(':') {
+                        self.integer()
+                    } else {
+                        left
+                    };
+                    self.expect(']');
+                    (left, right)
+                } else {
+                    (wire.left, wire.right)
+                };
+                let indices: Vec<i64> = match indices {
+                    (left, right) if left >= right => (right..=left).collect(),
+                    (left, right) => (left..=right).rev().collect(),
+                };
+                indices
+                    .into_iter()
+                    .map(|idx| match wire.net(idx) {
+                        Some(net) => Bit::Net(net),
+                        None => self.error(&format!("{}[{}] is out of range", name, idx)),
+                    })
+                    .collect()
+            }
+            t => {
+                self.pos -= 1;
+                self.error(&format!("Unexpected {:?} in expression", t))
+            }
+        }
+    }
+
+    // `input [3:0] a, b;`, `wire c = d;`, ...
+    fn declaration(&mut self, module: &mut Module) {
+        let direction = self.direction();
+        self.net_type();
+        let range = self.range();
+        loop {
+            let name = self.ident();
+            module.declare(&name, range);
+            if let Some(direction) = &direction {
+                module.directions.insert(name.clone(), direction.clone());
+            }
+            if self.accept('=') {
+                let rhs = self.expression(module);
+                module.assignments.push((module.wires[&name].bits(), rhs));
+            }
+            if !self.accept(',') {
+                break;
+            }
+        }
+        self.expect(';');
+    }
+
+    fn assignments(&mut self, module: &mut Module) {
+        loop {
+            let lhs = self.expression(module);
+            self.expect('=');
+            let rhs = self.expression(module);
+            module.assignments.push((lhs, rhs));
+            if !self.accept(',') {
+                break;
+            }
+        }
+        self.expect(';');
+    }
+
+    // `AND g1 (.A(a), .B(b), .Y(y));`, parameters of the instance are ignored.
+    fn instances(&mut self, module: &mut Module) {
+        let line = self.line();
+        let cell_type = self.ident();
+        if self.accept('#') {
+            self.skip_parens();
+        }
+        loop {
+            let name = self.ident();
+            if self.peek() == Some(&Token::Symbol('[')) {
+                self.error("Arrays of instances are not supported");
+            }
+            self.expect('(');
+            let mut connections = Vec::new();
+            if !self.accept(')') {
+                loop {
+                    if !self.accept('.') {
+                        self.error("Only named port connections like .A(a) are supported");
+                    }
+                    let port = self.ident();
+                    self.expect('(');
+                    // Unconnected ports are left out, like in yosys JSON files.
+                    if !self.accept(')') {
+                        let bits = self.expression(module);
+                        self.expect(')');
+                        connections.push((port, bits));
+                    }
+                    if !self.accept(',') {
+                        break;
+                    }
+                }
+                self.expect(')');
+            }
+            module.instances.push(Instance {
+                cell_type: cell_type.clone(),
+                name,
+                connections,
+                line,
+            });
+            if !self.accept(',') {
+                break;
+            }
+        }
+        self.expect(';');
+    }
+
+    fn module(&mut self) -> Module {
+        let mut module = Module {
+            name: self.ident(),
+            ..Default::default()
+        };
+        if self.peek() == Some(&Token::Symbol('#')) {
+            self.error("Module parameters are not supported");
+        }
+
+        // Either a list of names or ANSI style declarations.
+        if self.accept('(') && !self.accept(')') {
+            let mut declaration = None;
+            loop {
+                if let Some(direction) = self.direction() {
+                    self.net_type();
+                    declaration = Some((direction, self.range()));
+                }
+                let name = self.ident();
+                if let Some((direction, range)) = &declaration {
+                    module.declare(&name, *range);
+                    module.directions.insert(name.clone(), direction.clone());
+                }
+                module.ports.push(name);
+                if !self.accept(',') {
+                    break;
+                }
+            }
+            self.expect(')');
+        }
+        self.expect(';');
+
+        loop {
+            let keyword = match self.peek() {
+                Some(Token::Ident(keyword)) => keyword.clone(),
+                Some(t) => self.error(&format!("Unexpected {:?}", t)),
+                None => self.error(&format!("Module {} has no endmodule", module.name)),
+            };
+            match keyword.as_str() {
+                "endmodule" => {
+                    self.pos += 1;
+                    return module;
+                }
+                "input" | "output" | "inout" | "wire" | "reg" => self.declaration(&mut module),
+                "assign" => {
+                    self.pos += 1;
+                    self.assignments(&mut module);
+                }
+                "parameter" | "localparam" | "always" | "initial" | "generate" | "genvar"
+                | "integer" | "function" | "task" | "supply0" | "supply1" => self.error(&format!(
+                    "'{}' is not supported, only gate-level netlists can be read",
+                    keyword
+                )),
+                _ => self.instances(&mut module),
+            }
+        }
+    }
+}
+
+pub fn parse_verilog(filepath: &str, library: &CellLibrary) -> std::io::Result<YosysJson> {
+    let mut file = File::open(filepath)?;
+    let mut buf = String::new();
+    file.read_to_string(&mut buf)?;
+    Ok(parse(filepath, &buf, library))
+}
+
+fn parse(filepath: &str, buf: &str, library: &CellLibrary) -> YosysJson {
+    let mut parser = Parser {
+        filepath,
+        tokens: tokenize(buf),
+        pos: 0,
+    };
+    let mut modules = Vec::new();
+    while parser.peek().is_some() {
+        if !parser.accept_keyword("module") {
+            parser.error("Expected a module");
+        }
+        modules.push(parser.module());
+    }
+
+    let is_cell = |name: &str| {
+        library.get(name).is_some()
+            || CellAlias::get(name).is_some()
+            || BasicCircuitYada::try_from(name).is_ok()
+    };
+    modules.retain(|m| !is_cell(&m.name));
+    let names = modules.iter().map(|m| m.name.clone()).collect::<Vec<_>>();
+    let is_module = |name: &str| names.iter().any(|n| n == name);
+    let modules = modules
+        .into_iter()
+        .map(|m| (m.name.clone(), m.into_module(filepath, library, &is_module)))
+        .collect();
+    YosysJson::new("Verilog reader", modules)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parser(source: &str) -> Parser<'static> {
+        Parser {
+            filepath: "test.v",
+            tokens: tokenize(source),
+            pos: 0,
+        }
+    }
+
+    fn constants(values: &str) -> Vec<Bit> {
+        values
+            .chars()
+            .map(|c| match c {
+                '0' => Bit::Constant("0"),
+                '1' => Bit::Constant("1"),
+                _ => Bit::Constant("x"),
+            })
+            .collect()
+    }
+
+    // `wire [7:0] a;` and `wire [0:3] b;`
+    fn module() -> Module {
+        let mut module = Module::default();
+        module.declare("a", Some((7, 0)));
+        module.declare("b", Some((0, 3)));
+        module
+    }
+
+    fn nets(nets: &[usize]) -> Vec<Bit> {
+        nets.iter().map(|&net| Bit::Net(net)).collect()
+    }
+
+    #[test]
+    fn sized_literals() {
+        let p = parser("");
+        assert_eq!(p.number("4'b1x01"), constants("10x1"));
+        assert_eq!(p.number("3'd5"), constants("101"));
+        assert_eq!(p.number("6'o17"), constants("111100"));
+        assert_eq!(p.number("8'h_a"), constants("01010000"));
+        assert_eq!(p.number("2'b1"), constants("10"));
+    }
+
+    #[test]
+    fn unknown_literals() {
+        let p = parser("");
+        assert_eq!(p.number("8'hx"), constants("xxxxxxxx"));
+        assert_eq!(p.number("4'bx1"), constants("1xxx"));
+        assert_eq!(p.number("3'bz"), constants("xxx"));
+    }
+
+    #[test]
+    fn unsized_literals() {
+        let p = parser("");
+        let mut five = constants("101");
+        five.resize(32, Bit::Constant("0"));
+        assert_eq!(p.number("5"), five);
+        assert_eq!(p.number("'d5"), five);
+        assert_eq!(p.number("'sh5"), five);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid number")]
+    fn invalid_literal() {
+        parser("").number("4'b12");
+    }
+
+    #[test]
+    fn part_selects() {
+        let mut module = module();
+        assert_eq!(
+            parser("a").expression(&mut module),
+            nets(&[0, 1, 2, 3, 4, 5, 6, 7])
+        );
+        assert_eq!(
+            parser("a[5:2]").expression(&mut module),
+            nets(&[2, 3, 4, 5])
+        );
+        assert_eq!(parser("a[3]").expression(&mut module), nets(&[3]));
+        // b[3] is the least significant bit.
+        assert_eq!(parser("b").expression(&mut module), nets(&[8, 9, 10, 11]));
+        assert_eq!(parser("b[1:2]").expression(&mut module), nets(&[9, 10]));
+    }
+
+    #[test]
+    #[should_panic(expected = "a[8] is out of range")]
+    fn out_of_range() {
+        parser("a[8]").expression(&mut module());
+    }
+
+    #[test]
+    fn concatenation() {
+        let mut module = module();
+        let mut bits = constants("1");
+        bits.extend(nets(&[0, 1]));
+        assert_eq!(parser("{a[1], a[0], 1'b1}").expression(&mut module), bits);
+        assert_eq!(
+            parser("{2{a[1:0]}}").expression(&mut module),
+            nets(&[0, 1, 0, 1])
+        );
+    }
+
+    #[test]
+    fn escaped_identifiers() {
+        let tokens = tokenize("\\a[0] \\b+c ;");
+        assert_eq!(
+            tokens.into_iter().map(|(t, _)| t).collect::<Vec<_>>(),
+            vec![
+                Token::Ident("a[0]".to_string()),
+                Token::Ident("b+c".to_string()),
+                Token::Symbol(';'),
+            ]
+        );
+    }
+
+    #[test]
+    fn attributes_and_comments() {
+        let tokens =
+            tokenize("(* keep,\n src = \"a.v:1\" *) /* x\n */ AND // y\n`timescale 1ns\ng");
+        assert_eq!(
+            tokens,
+            vec![
+                (Token::Ident("AND".to_string()), 3),
+                (Token::Ident("g".to_string()), 5),
+            ]
+        );
+    }
+
+    #[test]
+    fn netlist() {
+        let json = parse(
+            "test.v",
+            "module top(input [1:0] a, output y, output [1:0] z);\n\
+             (* keep *) AND g (.A(a[0]), .B(a[1]), .Y(y));\n\
+             assign z = 2'b1x;\n\
+             endmodule\n",
+            &CellLibrary::default(),
+        );
+        let module = &json.modules["top"];
+        let cell = &module.cells["g"];
+        assert_eq!(cell.cell_type, "AND");
+        assert_eq!(
+            cell.connections["A"],
+            vec![module.ports["a"].bits[0].clone()]
+        );
+        assert_eq!(
+            cell.connections["B"],
+            vec![module.ports["a"].bits[1].clone()]
+        );
+        assert_eq!(cell.connections["Y"], module.ports["y"].bits);
+        assert_eq!(
+            module.ports["z"].bits,
+            vec![Value::from("x"), Value::from("1")]
+        );
+    }
+}